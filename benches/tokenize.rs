@@ -0,0 +1,39 @@
+use std::time::{Duration, Instant};
+use maple_lang::tokenizer::tokenize;
+
+// A function of `lines` lines using every kind of token, comments and string literals included.
+fn program(lines: usize) -> String {
+    let mut src = String::from("fn main {\n    let a = 0;\n");
+    for i in 0..lines {
+        src.push_str(&format!("    a += {} * 0x1f - 2.5e3 // 7; /* {} */ print(\"line\\t{}\"); # done\n", i, i, i));
+    }
+    src.push_str("    a @\n}\n");
+    src
+}
+
+// The fastest of a few runs, which is the least disturbed by whatever else the machine is doing.
+fn time(src: &str) -> Duration {
+    (0..5).map(|_| {
+        let start = Instant::now();
+        let tokens = tokenize(src).unwrap();
+        let elapsed = start.elapsed();
+        assert!(tokens.len() > src.len() / 10);
+        elapsed
+    }).min().unwrap()
+}
+
+// Tokenizes programs of doubling size up to 50k lines and fails unless the time per line stays about the same.
+fn main() {
+    let sizes = [6_250, 12_500, 25_000, 50_000];
+    let mut per_line = Vec::new();
+    for lines in sizes {
+        let elapsed = time(&program(lines));
+        per_line.push(elapsed.as_secs_f64() / lines as f64);
+        println!("{:>6} lines: {:>10.3?} ({:.0} ns per line)", lines, elapsed, per_line.last().unwrap() * 1e9);
+    }
+
+    // Eight times the source should take about eight times as long; a quadratic tokenizer would take sixty-four.
+    let growth = per_line.last().unwrap() / per_line.first().unwrap();
+    assert!(growth < 3.0, "the time per line grew {:.1} times from {} to {} lines", growth, sizes[0], sizes[sizes.len() - 1]);
+    println!("time per line grew {:.2} times from {} to {} lines", growth, sizes[0], sizes[sizes.len() - 1]);
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+use std::ops::Deref;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    UndefinedToken,
+    UnterminatedComment,
+    UnterminatedString,
+    UndefinedEscape,
+    InvalidNumber,
+    OutOfRangeNumber,
+    UnexpectedToken,
+    UndeclaredVariable,
+    DuplicateGlobal,
+    DuplicateLabel,
+    UndefinedLabel,
+    OutsideOfLoop,
+    NotAssignable,
+    NotConstant,
+    DivisionByZero,
+    ArrayReturned,
+    ArrayLength,
+    EmptyRange,
+    OverlappingArm,
+    UnreachableArm,
+    NonPositiveStep,
+    TypeMismatch,
+    FunctionNotFound,
+    InvalidArguments,
+    ReadFailed,
+    WriteFailed,
+    Internal,
+}
+
+impl ErrorKind {
+    // Lexer errors are E00xx, parser errors E01xx, type errors E02xx, command line and file errors E03xx; E0900 is a bug in the compiler itself.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::UndefinedToken => "E0001",
+            ErrorKind::UnterminatedComment => "E0002",
+            ErrorKind::UnterminatedString => "E0003",
+            ErrorKind::UndefinedEscape => "E0004",
+            ErrorKind::InvalidNumber => "E0005",
+            ErrorKind::OutOfRangeNumber => "E0006",
+            ErrorKind::UnexpectedToken => "E0100",
+            ErrorKind::UndeclaredVariable => "E0101",
+            ErrorKind::DuplicateGlobal => "E0102",
+            ErrorKind::DuplicateLabel => "E0103",
+            ErrorKind::UndefinedLabel => "E0104",
+            ErrorKind::OutsideOfLoop => "E0105",
+            ErrorKind::NotAssignable => "E0106",
+            ErrorKind::NotConstant => "E0107",
+            ErrorKind::DivisionByZero => "E0108",
+            ErrorKind::ArrayReturned => "E0109",
+            ErrorKind::ArrayLength => "E0110",
+            ErrorKind::EmptyRange => "E0111",
+            ErrorKind::OverlappingArm => "E0112",
+            ErrorKind::UnreachableArm => "E0113",
+            ErrorKind::NonPositiveStep => "E0114",
+            ErrorKind::TypeMismatch => "E0200",
+            ErrorKind::FunctionNotFound => "E0201",
+            ErrorKind::InvalidArguments => "E0300",
+            ErrorKind::ReadFailed => "E0301",
+            ErrorKind::WriteFailed => "E0302",
+            ErrorKind::Internal => "E0900",
+        }
+    }
+}
+
+// A range of the source, from `line:pos` up to but not including `end_line:end_pos`; `file` is filled in by `main`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    pub file: String,
+    pub line: usize,
+    pub pos: usize,
+    pub end_line: usize,
+    pub end_pos: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, pos: usize, end_line: usize, end_pos: usize) -> Self {
+        Span { file: String::new(), line, pos, end_line, end_pos }
+    }
+
+    pub fn at(line: usize, pos: usize) -> Self {
+        Span::new(line, pos, line, pos)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+// Boxed so that every `Result` carrying one stays a pointer wide.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError(Box<Diagnostic>);
+
+impl CompileError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>, span: Span) -> Self {
+        CompileError(Box::new(Diagnostic { kind, message: message.into(), span: Some(span), labels: Vec::new(), notes: Vec::new(), help: None }))
+    }
+
+    // An error raised where the position is not known, such as inside constant folding; the caller locates it afterwards.
+    pub fn unlocated(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CompileError(Box::new(Diagnostic { kind, message: message.into(), span: None, labels: Vec::new(), notes: Vec::new(), help: None }))
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        CompileError::unlocated(ErrorKind::Internal, message)
+    }
+
+    pub fn located(mut self, span: Span) -> Self {
+        self.0.span.get_or_insert(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.0.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.0.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.0.help = Some(help.into());
+        self
+    }
+
+    pub fn in_file(mut self, file: &str) -> Self {
+        let diagnostic = &mut *self.0;
+        for span in diagnostic.span.iter_mut().chain(diagnostic.labels.iter_mut().map(|label| &mut label.span)) {
+            span.file = file.to_string();
+        }
+        self
+    }
+}
+
+impl CompileError {
+    // Formats the error like rustc does, quoting the lines of `src` it points at; lines and columns are shown 1-based.
+    pub fn render(&self, src: &str, color: bool) -> String {
+        let paint = |text: &str, style: &str| if color { format!("\x1b[{}m{}\x1b[0m", style, text) } else { text.to_string() };
+
+        let mut out = format!("{}{}\n", paint(&format!("error[{}]", self.kind.code()), "1;31"), paint(&format!(": {}", self.message), "1"));
+
+        let mut marks = Vec::new();
+        if let Some(span) = &self.span {
+            marks.push((span, true, ""));
+        }
+        marks.extend(self.labels.iter().map(|label| (&label.span, false, label.message.as_str())));
+
+        let width = marks.iter().map(|(span, _, _)| (span.line + 1).to_string().len()).max().unwrap_or(0);
+        let gutter = " ".repeat(width);
+
+        if let Some(span) = &self.span {
+            out.push_str(&format!("{}{} {}:{}:{}\n", gutter, paint("-->", "1;34"), span.file, span.line + 1, span.pos + 1));
+            out.push_str(&format!("{} {}\n", gutter, paint("|", "1;34")));
+
+            let lines = src.lines().collect::<Vec<&str>>();
+            let mut quoted = marks.iter().map(|(span, _, _)| span.line).collect::<Vec<usize>>();
+            quoted.sort();
+            quoted.dedup();
+            for line in quoted {
+                // Tabs count as one column in the tokenizer, so they are shown as one space to keep the carets aligned.
+                let text = lines.get(line).copied().unwrap_or("").replace('\t', " ");
+                out.push_str(&format!("{} {} {}\n", paint(&format!("{:>width$}", line + 1), "1;34"), paint("|", "1;34"), text));
+
+                for (span, primary, message) in marks.iter().filter(|(span, _, _)| span.line == line) {
+                    let end = if span.end_line == line { span.end_pos } else { text.chars().count() };
+                    let underline = (if *primary { "^" } else { "-" }).repeat(end.saturating_sub(span.pos).max(1));
+                    let message = if message.is_empty() { String::new() } else { format!(" {}", message) };
+                    out.push_str(&format!("{} {} {}{}\n", gutter, paint("|", "1;34"), " ".repeat(span.pos), paint(&format!("{}{}", underline, message), if *primary { "1;31" } else { "1;34" })));
+                }
+            }
+        }
+
+        if self.span.is_some() && (!self.notes.is_empty() || self.help.is_some()) {
+            out.push_str(&format!("{} {}\n", gutter, paint("|", "1;34")));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} {} {}: {}\n", gutter, paint("=", "1;34"), paint("note", "1"), note));
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{} {} {}: {}\n", gutter, paint("=", "1;34"), paint("help", "1"), help));
+        }
+
+        out
+    }
+}
+
+impl Deref for CompileError {
+    type Target = Diagnostic;
+
+    fn deref(&self) -> &Diagnostic {
+        &self.0
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error[{}]: {}", self.kind.code(), self.message)?;
+        if let Some(span) = &self.span {
+            write!(f, " ({}:{}:{})", span.file, span.line + 1, span.pos + 1)?;
+        }
+        for label in &self.labels {
+            write!(f, "\n  {}:{}:{}: {}", label.span.file, label.span.line + 1, label.span.pos + 1, label.message)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  note: {}", note)?;
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n  help: {}", help)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,14 @@
 use std::path::Path;
+use maple_lang::compile_error::{CompileError, ErrorKind};
 
-pub fn check_args(args: &Vec<String>) -> Option<String> {
-    if !check_len(args) { return Some("One argument required.".to_string()); }
-    if !check_source_path(&args[1]) { return Some("Wrong with source path.".to_string()); }
+pub fn check_args(args: &[String]) -> Result<(), CompileError> {
+    if !check_len(args) { return Err(CompileError::unlocated(ErrorKind::InvalidArguments, "expected a source path and an output path").with_help("run it as `maple_lang <source> <output>`")); }
+    if !check_source_path(&args[1]) { return Err(CompileError::unlocated(ErrorKind::InvalidArguments, format!("source file `{}` does not exist", args[1]))); }
 
-    None
+    Ok(())
 }
 
-fn check_len(args: &Vec<String>) -> bool {
+fn check_len(args: &[String]) -> bool {
     args.len() >= 3
 }
 
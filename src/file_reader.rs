@@ -1,6 +1,7 @@
 use std::fs;
 use std::path::Path;
+use maple_lang::compile_error::{CompileError, ErrorKind};
 
-pub fn read<P: AsRef<Path>>(path: P) -> String {
-    fs::read_to_string(path).unwrap()
+pub fn read<P: AsRef<Path>>(path: P) -> Result<String, CompileError> {
+    fs::read_to_string(&path).map_err(|error| CompileError::unlocated(ErrorKind::ReadFailed, format!("cannot read `{}`: {}", path.as_ref().display(), error)))
 }
\ No newline at end of file
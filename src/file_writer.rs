@@ -1,8 +1,10 @@
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
+use maple_lang::compile_error::{CompileError, ErrorKind};
 
-pub fn write<P: AsRef<Path>>(path: P, context: String) {
-    let mut file = File::create(&path).unwrap();
-    file.write_all(context.as_bytes()).unwrap();
+pub fn write<P: AsRef<Path>>(path: P, context: String) -> Result<(), CompileError> {
+    let error = |error| CompileError::unlocated(ErrorKind::WriteFailed, format!("cannot write `{}`: {}", path.as_ref().display(), error));
+    let mut file = File::create(&path).map_err(error)?;
+    file.write_all(context.as_bytes()).map_err(error)
 }
\ No newline at end of file
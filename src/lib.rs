@@ -0,0 +1,18 @@
+pub mod compile_error;
+pub mod tokenizer;
+mod parser;
+mod type_checker;
+mod llvm_generator;
+
+use crate::compile_error::CompileError;
+
+// Every error found while parsing is reported together; the other stages stop at their first one.
+pub fn compile(src: &str) -> Result<String, Vec<CompileError>> {
+    let tokens = tokenizer::tokenize(src).map_err(|error| vec![error])?;
+    let (program, errors) = parser::parse(tokens);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    type_checker::check(&program).map_err(|error| vec![error])?;
+    llvm_generator::generate(program).map_err(|error| vec![error])
+}
@@ -1,362 +1,987 @@
 use std::collections::VecDeque;
+use crate::compile_error::{CompileError, ErrorKind};
 use crate::parser::node::Node;
+use crate::parser::node::logical::Logical;
 use crate::parser::node::operator::Operator;
+use crate::parser::node::value_type::ValueType;
 
-pub fn generate(program: Node) -> Result<String, String> {
-    if let Node::Program { functions } = program {
+struct Context<'a> {
+    functions: &'a Vec<(String, Vec<ValueType>, ValueType)>,
+    globals: &'a Vec<(String, ValueType)>,
+    strings: &'a mut Vec<Vec<u8>>,
+    stack: VecDeque<(usize, ValueType)>,
+    last_index: usize,
+    last_label: usize,
+    loops: Vec<(Option<String>, String, String)>,
+    variable_types: Vec<Option<ValueType>>,
+    return_type: ValueType,
+    arrays: Vec<(ValueType, usize)>,
+}
+
+pub fn generate(program: Node) -> Result<String, CompileError> {
+    if let Node::Program { globals, functions, span: _ } = program {
         let mut code = String::new();
 
+        let mut strings = Vec::new();
+        let mut global_info = Vec::new();
+        let mut global_code = String::new();
+        for global in &globals {
+            if let Node::Global { name, typ, value, span: _ } = global {
+                global_code.push_str(&global_definition(name, *typ, value, &mut strings)?);
+                global_info.push((name.clone(), *typ));
+            } else {
+                return Err(CompileError::internal("Not a global"));
+            }
+        }
+
         let mut function_info = Vec::new();
-        function_info.push((format!("debug"), 1));
+        function_info.push(("debug".to_string(), vec![ValueType::Int], ValueType::Int));
+        function_info.push(("print".to_string(), vec![ValueType::Int], ValueType::Int));
         for function in &functions {
-            if let Node::Function { name, args_num, variables: _, statement: _ } = function {
-                function_info.push((name.clone(), *args_num))
+            if let Node::Function { name, arg_types, return_type, variables: _, statement: _, span: _ } = function {
+                function_info.push((name.clone(), arg_types.clone(), *return_type))
             } else {
-                return Err(format!("Not a function"));
+                return Err(CompileError::internal("Not a function"));
             }
         }
 
         code.push_str("declare i32 @printf(i8*, ...)\n");
-        code.push_str("@str = constant [4 x i8] c\"%d\\0A\\00\"\n");
-        code.push_str("define i64 @debug(i64 %n) {\n");
-        code.push_str("entry:\n");
-        code.push_str("  %0 = getelementptr [4 x i8], [4 x i8]* @str, i32 0, i32 0\n");
-        code.push_str("  %1 = call i32 (i8*, ...) @printf(i8* %0, i64 %n)\n");
-        code.push_str("  %2 = zext i32 %1 to i64\n");
-        code.push_str("  ret i64 %2\n");
-        code.push_str("}\n");
+        code.push_str("declare void @llvm.memset.p0i8.i64(i8*, i8, i64, i1)\n");
+        code.push_str(&print_function("debug", "i64", "%ld\\0A"));
+        code.push_str(&print_function("debug.float", "double", "%.15g\\0A"));
+        code.push_str(&print_function("debug.str", "i8*", "%s\\0A"));
+        code.push_str(&print_function("print", "i64", "%ld"));
+        code.push_str(&print_function("print.float", "double", "%.15g"));
+        code.push_str(&print_function("print.str", "i8*", "%s"));
+        code.push_str(&power_function());
+        code.push_str(&root_function());
 
+        let mut function_code = String::new();
         for function in &functions {
-            let mut stack = VecDeque::new();
-            let mut i = 0;
-            let mut l = 0;
-            code.push_str(&gen(function, &mut stack, &function_info, &mut i, &mut l)?);
-            if !stack.is_empty() {
-                return Err(format!("Stack not empty"));
+            let mut context = Context {
+                functions: &function_info,
+                globals: &global_info,
+                strings: &mut strings,
+                stack: VecDeque::new(),
+                last_index: 0,
+                last_label: 0,
+                loops: Vec::new(),
+                variable_types: Vec::new(),
+                return_type: ValueType::Int,
+                arrays: Vec::new(),
+            };
+            function_code.push_str(&gen(function, &mut context)?);
+            if !context.stack.is_empty() {
+                return Err(CompileError::internal("Stack not empty"));
             }
         }
 
+        for (i, string) in strings.iter().enumerate() {
+            code.push_str(&format!("@str.{} = private constant [{} x i8] c\"{}\"\n", i, string.len() + 1, escape(string)));
+        }
+        code.push_str(&global_code);
+        code.push_str(&function_code);
+
         Ok(code)
     } else {
-        Err(format!("Not a program"))
+        Err(CompileError::internal("Not a program"))
     }
 }
 
-fn gen(node: &Node, stack: &mut VecDeque<usize>, functions: &Vec<(String, usize)>, last_index: &mut usize, last_label: &mut usize) -> Result<String, String> {
+// `@global.{name}` holds the value; an array points into its own zeroed `@global.{name}.array`.
+fn global_definition(name: &str, typ: ValueType, value: &Node, strings: &mut Vec<Vec<u8>>) -> Result<String, CompileError> {
+    let mut code = String::new();
+
+    let initializer = match value {
+        Node::Number { num, span: _ } => num.to_string(),
+        Node::Float { num, span: _ } => format!("0x{:016X}", num.to_bits()),
+        Node::Str { text, span: _ } => {
+            let index = string_index(strings, text);
+            format!("getelementptr ([{} x i8], [{} x i8]* @str.{}, i64 0, i64 0)", text.len() + 1, text.len() + 1, index)
+        },
+        Node::Array { typ, len, span: _ } => {
+            let element = llvm_type(typ.element().unwrap());
+            code.push_str(&format!("@global.{}.array = global [{} x {}] zeroinitializer\n", name, len, element));
+            format!("getelementptr ([{} x {}], [{} x {}]* @global.{}.array, i64 0, i64 0)", len, element, len, element, name)
+        },
+        _ => return Err(CompileError::internal("Not a constant")),
+    };
+    code.push_str(&format!("@global.{} = global {} {}\n", name, llvm_type(typ), initializer));
+
+    Ok(code)
+}
+
+// A printf wrapper that returns the number of characters written, like `debug`.
+fn print_function(name: &str, typ: &str, format: &str) -> String {
+    let len = format.len() - format.matches('\\').count() * 2 + 1;
+    let mut code = String::new();
+
+    code.push_str(&format!("@str.{} = private constant [{} x i8] c\"{}\\00\"\n", name, len, format));
+    code.push_str(&format!("define i64 @{}({} %n) {{\n", name, typ));
+    code.push_str("entry:\n");
+    code.push_str(&format!("  %0 = getelementptr [{} x i8], [{} x i8]* @str.{}, i32 0, i32 0\n", len, len, name));
+    code.push_str(&format!("  %1 = call i32 (i8*, ...) @printf(i8* %0, {} %n)\n", typ));
+    code.push_str("  %2 = zext i32 %1 to i64\n");
+    code.push_str("  ret i64 %2\n");
+    code.push_str("}\n");
+
+    code
+}
+
+// `b ** e` by square-and-multiply, wrapping on overflow.
+// A negative exponent truncates `1 / b ** -e` toward zero, so it is 0 unless `b` is 1 or -1 (`0 ** -e` is also 0).
+fn power_function() -> String {
+    let mut code = String::new();
+
+    code.push_str("define i64 @builtin.power(i64 %b, i64 %e) {\n");
+    code.push_str("entry:\n");
+    code.push_str("  %negative = icmp slt i64 %e, 0\n");
+    code.push_str("  br i1 %negative, label %reciprocal, label %loop\n");
+    code.push_str("reciprocal:\n");
+    code.push_str("  %odd = trunc i64 %e to i1\n");
+    code.push_str("  %sign = select i1 %odd, i64 -1, i64 1\n");
+    code.push_str("  %is_minus_one = icmp eq i64 %b, -1\n");
+    code.push_str("  %minus_one_case = select i1 %is_minus_one, i64 %sign, i64 0\n");
+    code.push_str("  %is_one = icmp eq i64 %b, 1\n");
+    code.push_str("  %reciprocal_result = select i1 %is_one, i64 1, i64 %minus_one_case\n");
+    code.push_str("  ret i64 %reciprocal_result\n");
+    code.push_str("loop:\n");
+    code.push_str("  %acc = phi i64 [ 1, %entry ], [ %next_acc, %body ]\n");
+    code.push_str("  %base = phi i64 [ %b, %entry ], [ %next_base, %body ]\n");
+    code.push_str("  %exp = phi i64 [ %e, %entry ], [ %next_exp, %body ]\n");
+    code.push_str("  %done = icmp eq i64 %exp, 0\n");
+    code.push_str("  br i1 %done, label %end, label %body\n");
+    code.push_str("body:\n");
+    code.push_str("  %bit = trunc i64 %exp to i1\n");
+    code.push_str("  %mul = mul i64 %acc, %base\n");
+    code.push_str("  %next_acc = select i1 %bit, i64 %mul, i64 %acc\n");
+    code.push_str("  %next_base = mul i64 %base, %base\n");
+    code.push_str("  %next_exp = lshr i64 %exp, 1\n");
+    code.push_str("  br label %loop\n");
+    code.push_str("end:\n");
+    code.push_str("  ret i64 %acc\n");
+    code.push_str("}\n");
+
+    code
+}
+
+// `x // n` is the n-th root of `x` truncated toward zero, so `-27 // 3` is -3 and `10 // 2` is 3.
+// A negative index truncates `1 / (x // -n)` toward zero.
+// An even root of a negative number and a zero index have no integer answer and give 0.
+fn root_function() -> String {
+    let mut code = String::new();
+
+    code.push_str("declare { i64, i1 } @llvm.umul.with.overflow.i64(i64, i64)\n");
+    // Whether `r ** n <= u` for unsigned `u`, bailing out as soon as the product exceeds `u` or overflows.
+    code.push_str("define i1 @builtin.root.fits(i64 %r, i64 %n, i64 %u) {\n");
+    code.push_str("entry:\n");
+    code.push_str("  br label %loop\n");
+    code.push_str("loop:\n");
+    code.push_str("  %acc = phi i64 [ 1, %entry ], [ %next_acc, %check ]\n");
+    code.push_str("  %i = phi i64 [ 0, %entry ], [ %next_i, %check ]\n");
+    code.push_str("  %done = icmp eq i64 %i, %n\n");
+    code.push_str("  br i1 %done, label %fits, label %body\n");
+    code.push_str("body:\n");
+    code.push_str("  %mul = call { i64, i1 } @llvm.umul.with.overflow.i64(i64 %acc, i64 %r)\n");
+    code.push_str("  %next_acc = extractvalue { i64, i1 } %mul, 0\n");
+    code.push_str("  %overflow = extractvalue { i64, i1 } %mul, 1\n");
+    code.push_str("  %next_i = add i64 %i, 1\n");
+    code.push_str("  br i1 %overflow, label %too_large, label %check\n");
+    code.push_str("check:\n");
+    code.push_str("  %exceeds = icmp ugt i64 %next_acc, %u\n");
+    code.push_str("  br i1 %exceeds, label %too_large, label %loop\n");
+    code.push_str("fits:\n");
+    code.push_str("  ret i1 1\n");
+    code.push_str("too_large:\n");
+    code.push_str("  ret i1 0\n");
+    code.push_str("}\n");
+
+    code.push_str("define i64 @builtin.root(i64 %x, i64 %n) {\n");
+    code.push_str("entry:\n");
+    code.push_str("  %negative_index = icmp slt i64 %n, 0\n");
+    code.push_str("  br i1 %negative_index, label %reciprocal, label %positive\n");
+    // The magnitude of a negative index, read as unsigned so that i64::MIN gives 2 ** 63 rather than itself.
+    code.push_str("reciprocal:\n");
+    code.push_str("  %index = sub i64 0, %n\n");
+    code.push_str("  %r = call i64 @builtin.root.index(i64 %x, i64 %index)\n");
+    code.push_str("  %is_minus_one = icmp eq i64 %r, -1\n");
+    code.push_str("  %minus_one_case = select i1 %is_minus_one, i64 -1, i64 0\n");
+    code.push_str("  %is_one = icmp eq i64 %r, 1\n");
+    code.push_str("  %reciprocal_result = select i1 %is_one, i64 1, i64 %minus_one_case\n");
+    code.push_str("  ret i64 %reciprocal_result\n");
+    code.push_str("positive:\n");
+    code.push_str("  %positive_result = call i64 @builtin.root.index(i64 %x, i64 %n)\n");
+    code.push_str("  ret i64 %positive_result\n");
+    code.push_str("}\n");
+
+    // The root of an unsigned index `%n`.
+    code.push_str("define i64 @builtin.root.index(i64 %x, i64 %n) {\n");
+    code.push_str("entry:\n");
+    code.push_str("  %zero_index = icmp eq i64 %n, 0\n");
+    code.push_str("  br i1 %zero_index, label %undefined, label %sign\n");
+    code.push_str("sign:\n");
+    code.push_str("  %negative = icmp slt i64 %x, 0\n");
+    code.push_str("  %odd = trunc i64 %n to i1\n");
+    code.push_str("  %even = xor i1 %odd, 1\n");
+    code.push_str("  %even_of_negative = and i1 %negative, %even\n");
+    code.push_str("  br i1 %even_of_negative, label %undefined, label %abs\n");
+    code.push_str("undefined:\n");
+    code.push_str("  ret i64 0\n");
+    // |x| as unsigned, so that the magnitude of i64::MIN is still representable.
+    code.push_str("abs:\n");
+    code.push_str("  %negated = sub i64 0, %x\n");
+    code.push_str("  %u = select i1 %negative, i64 %negated, i64 %x\n");
+    code.push_str("  %first = icmp eq i64 %n, 1\n");
+    code.push_str("  br i1 %first, label %end, label %large\n");
+    code.push_str("large:\n");
+    // 2 ** 64 exceeds every u64, so an index of 64 or more only leaves 0 and 1.
+    code.push_str("  %huge_index = icmp uge i64 %n, 64\n");
+    code.push_str("  br i1 %huge_index, label %unit, label %search\n");
+    code.push_str("unit:\n");
+    code.push_str("  %nonzero = icmp ne i64 %u, 0\n");
+    code.push_str("  %unit_result = zext i1 %nonzero to i64\n");
+    code.push_str("  br label %end\n");
+    // Largest r in [0, 2 ** 32) with r ** n <= u, since every root of index 2 or more fits in 32 bits.
+    code.push_str("search:\n");
+    code.push_str("  %lo = phi i64 [ 0, %large ], [ %next_lo, %step ]\n");
+    code.push_str("  %hi = phi i64 [ 4294967295, %large ], [ %next_hi, %step ]\n");
+    code.push_str("  %found = icmp uge i64 %lo, %hi\n");
+    code.push_str("  br i1 %found, label %end, label %step\n");
+    code.push_str("step:\n");
+    code.push_str("  %sum = add i64 %lo, %hi\n");
+    code.push_str("  %sum_up = add i64 %sum, 1\n");
+    code.push_str("  %mid = lshr i64 %sum_up, 1\n");
+    code.push_str("  %fits = call i1 @builtin.root.fits(i64 %mid, i64 %n, i64 %u)\n");
+    code.push_str("  %mid_down = sub i64 %mid, 1\n");
+    code.push_str("  %next_lo = select i1 %fits, i64 %mid, i64 %lo\n");
+    code.push_str("  %next_hi = select i1 %fits, i64 %hi, i64 %mid_down\n");
+    code.push_str("  br label %search\n");
+    code.push_str("end:\n");
+    code.push_str("  %root = phi i64 [ %u, %abs ], [ %unit_result, %unit ], [ %lo, %search ]\n");
+    code.push_str("  %negated_root = sub i64 0, %root\n");
+    code.push_str("  %result = select i1 %negative, i64 %negated_root, i64 %root\n");
+    code.push_str("  ret i64 %result\n");
+    code.push_str("}\n");
+
+    // Floats go through `pow`, except that an odd integral index also takes the real root of a negative number.
+    code.push_str("declare double @llvm.pow.f64(double, double)\n");
+    code.push_str("define double @builtin.root.float(double %x, double %n) {\n");
+    code.push_str("entry:\n");
+    code.push_str("  %exponent = fdiv double 1.0, %n\n");
+    code.push_str("  %integral = fptosi double %n to i64\n");
+    code.push_str("  %back = sitofp i64 %integral to double\n");
+    code.push_str("  %is_integral = fcmp oeq double %back, %n\n");
+    code.push_str("  %odd = trunc i64 %integral to i1\n");
+    code.push_str("  %negative = fcmp olt double %x, 0.0\n");
+    code.push_str("  %odd_integral = and i1 %is_integral, %odd\n");
+    code.push_str("  %mirror = and i1 %odd_integral, %negative\n");
+    code.push_str("  %negated = fneg double %x\n");
+    code.push_str("  %base = select i1 %mirror, double %negated, double %x\n");
+    code.push_str("  %root = call double @llvm.pow.f64(double %base, double %exponent)\n");
+    code.push_str("  %negated_root = fneg double %root\n");
+    code.push_str("  %result = select i1 %mirror, double %negated_root, double %root\n");
+    code.push_str("  ret double %result\n");
+    code.push_str("}\n");
+
+    code
+}
+
+fn gen(node: &Node, context: &mut Context) -> Result<String, CompileError> {
     let mut code = String::new();
 
     match node {
-        Node::Program { functions: _ } => {
-            return Err(format!("Error"));
+        Node::Program { globals: _, functions: _, span: _ } | Node::Global { name: _, typ: _, value: _, span: _ } => {
+            return Err(CompileError::internal("Error"));
         },
-        Node::Function { name, args_num, variables, statement } => {
-            code.push_str(&format!("define i64 @{}(", name));
+        Node::Function { name, arg_types, return_type, variables, statement, span: _ } => {
+            code.push_str(&format!("define {} @{}(", llvm_type(*return_type), name));
 
-            for i in 0..*args_num {
-                code.push_str(&format!("{}i64 %{}", if i == 0 { "" } else { ", " }, variables[i]));
+            for (i, (variable, typ)) in variables.iter().zip(arg_types).enumerate() {
+                code.push_str(&format!("{}{} %{}", if i == 0 { "" } else { ", " }, llvm_type(*typ), variable));
             }
 
             code.push_str(") {\n");
             code.push_str("entry:\n");
 
-            for i in 0..*args_num {
-                code.push_str(&format!("  %{} = alloca i64\n", i));
-                code.push_str(&format!("  store i64 %{}, i64* %{}\n", variables[i], i));
+            context.variable_types = vec![None; variables.len()];
+            for (variable_type, typ) in context.variable_types.iter_mut().zip(arg_types) {
+                *variable_type = Some(*typ);
             }
+            context.return_type = *return_type;
+            context.arrays = Vec::new();
+
+            context.last_index += variables.len();
+
+            let body = gen(statement, context)?;
 
-            for i in *args_num..variables.len() {
-                code.push_str(&format!("  %{} = alloca i64\n", i));
-                code.push_str(&format!("  store i64 0, i64* %{}\n", i));
+            // The slots are typed by their first store, so they are allocated once the body is known.
+            for (i, (variable, typ)) in variables.iter().zip(arg_types).enumerate() {
+                code.push_str(&format!("  %{} = alloca {}\n", i, llvm_type(*typ)));
+                code.push_str(&format!("  store {} %{}, {}* %{}\n", llvm_type(*typ), variable, llvm_type(*typ), i));
             }
 
-            *last_index += variables.len();
+            for (i, typ) in context.variable_types.iter().enumerate().skip(arg_types.len()) {
+                let typ = llvm_type(typ.unwrap_or(ValueType::Int));
+                code.push_str(&format!("  %{} = alloca {}\n", i, typ));
+                code.push_str(&format!("  store {} zeroinitializer, {}* %{}\n", typ, typ, i));
+            }
+
+            for (i, (typ, len)) in context.arrays.iter().enumerate() {
+                code.push_str(&format!("  %array{} = alloca [{} x {}]\n", i, len, llvm_type(*typ)));
+            }
 
-            code.push_str(&gen(statement, stack, functions, last_index, last_label)?);
+            code.push_str(&body);
 
-            code.push_str(&format!("  ret i64 0\n"));
+            code.push_str(&format!("  ret {} zeroinitializer\n", llvm_type(*return_type)));
             code.push_str("}\n");
         },
-        Node::Statement { node } => {
-            code.push_str(&gen(node.as_ref(), stack, functions, last_index, last_label)?);
-            stack.pop_back().unwrap();
+        Node::Statement { node, span: _ } => {
+            code.push_str(&gen(node.as_ref(), context)?);
+            context.stack.pop_back().unwrap();
         },
-        Node::Block { statements } => {
+        Node::Block { statements, span: _ } => {
             for node in statements {
-                code.push_str(&gen(node, stack, functions, last_index, last_label)?);
+                code.push_str(&gen(node, context)?);
             }
         }
-        Node::Return { node } => {
-            code.push_str(&gen(node.as_ref(), stack, functions, last_index, last_label)?);
-            code.push_str(&format!("  ret i64 %{}\n", stack.pop_back().unwrap()));
-            *last_index += 1;
+        Node::Return { node, span: _ } => {
+            code.push_str(&gen(node.as_ref(), context)?);
+            let value = context.stack.pop_back().unwrap();
+            let (convert_code, value) = convert(value, context.return_type, &mut context.last_index)?;
+            code.push_str(&convert_code);
+            code.push_str(&format!("  ret {} %{}\n", llvm_type(context.return_type), value));
+            context.last_index += 1;
         },
-        Node::If { condition, true_case, false_case } => {
-            let label = *last_label;
-            *last_label += 1;
-            code.push_str(&gen(condition.as_ref(), stack, functions, last_index, last_label)?);
-            code.push_str(&format!("  %{} = icmp ne i64 %{}, 0\n", last_index, stack.pop_back().unwrap()));
-            code.push_str(&format!("  br i1 %{}, label %then{}, label %else{}\n", last_index, label, label));
-            *last_index += 1;
+        Node::Let { offset, typ, value, span: _ } => {
+            code.push_str(&gen(value.as_ref(), context)?);
+            let value = context.stack.pop_back().unwrap();
+            let typ = typ.unwrap_or(value.1);
+            context.variable_types[*offset] = Some(typ);
+            let (convert_code, value) = convert(value, typ, &mut context.last_index)?;
+            code.push_str(&convert_code);
+            code.push_str(&format!("  store {} %{}, {}* %{}\n", llvm_type(typ), value, llvm_type(typ), offset));
+        },
+        Node::If { condition, true_case, false_case, span: _ } => {
+            let label = context.last_label;
+            context.last_label += 1;
+            code.push_str(&gen(condition.as_ref(), context)?);
+            code.push_str(&truth(context)?);
+            code.push_str(&format!("  br i1 %{}, label %then{}, label %else{}\n", context.last_index - 1, label, label));
             code.push_str(&format!("then{}:\n", label));
-            code.push_str(&gen(true_case.as_ref(), stack, functions, last_index, last_label)?);
+            code.push_str(&gen(true_case.as_ref(), context)?);
             code.push_str(&format!("  br label %end{}\n", label));
             code.push_str(&format!("else{}:\n", label));
             if let Some(false_case) = false_case.as_ref() {
-                code.push_str(&gen(false_case, stack, functions, last_index, last_label)?);
+                code.push_str(&gen(false_case, context)?);
             }
             code.push_str(&format!("  br label %end{}\n", label));
             code.push_str(&format!("end{}:\n", label));
         },
-        Node::For { init, condition, update, statement } => {
-            let label = *last_label;
-            *last_label += 1;
+        Node::For { label: loop_label, init, condition, update, statement, span: _ } => {
+            let label = context.last_label;
+            context.last_label += 1;
             if let Some(init) = init.as_ref() {
-                code.push_str(&gen(init, stack, functions, last_index, last_label)?);
-                stack.pop_back().unwrap();
+                code.push_str(&gen(init, context)?);
             }
             code.push_str(&format!("  br label %begin{}\n", label));
             code.push_str(&format!("begin{}:\n", label));
             if let Some(condition) = condition.as_ref() {
-                code.push_str(&gen(condition, stack, functions, last_index, last_label)?);
-                code.push_str(&format!("  %{} = icmp ne i64 %{}, 0\n", last_index, stack.pop_back().unwrap()));
-                code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", *last_index, label, label));
-                *last_index += 1;
+                code.push_str(&gen(condition, context)?);
+                code.push_str(&truth(context)?);
+                code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", context.last_index - 1, label, label));
             } else {
                 code.push_str(&format!("  br label %then{}\n", label));
             }
             code.push_str(&format!("then{}:\n", label));
-            code.push_str(&gen(statement.as_ref(), stack, functions, last_index, last_label)?);
+            context.loops.push((loop_label.clone(), format!("next{}", label), format!("end{}", label)));
+            code.push_str(&gen(statement.as_ref(), context)?);
+            context.loops.pop();
+            code.push_str(&format!("  br label %next{}\n", label));
+            code.push_str(&format!("next{}:\n", label));
             if let Some(update) = update.as_ref() {
-                code.push_str(&gen(update, stack, functions, last_index, last_label)?);
-                stack.pop_back().unwrap();
+                code.push_str(&gen(update, context)?);
+                context.stack.pop_back().unwrap();
             }
             code.push_str(&format!("  br label %begin{}\n", label));
             code.push_str(&format!("end{}:\n", label));
         },
-        Node::While { condition, node } => {
-            let label = *last_label;
-            *last_label += 1;
+        Node::While { label: loop_label, condition, node, span: _ } => {
+            let label = context.last_label;
+            context.last_label += 1;
             code.push_str(&format!("  br label %begin{}\n", label));
             code.push_str(&format!("begin{}:\n", label));
-            code.push_str(&gen(condition, stack, functions, last_index, last_label)?);
-            code.push_str(&format!("  %{} = icmp ne i64 %{}, 0\n", last_index, stack.pop_back().unwrap()));
-            code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", last_index, label, label));
-            *last_index += 1;
+            code.push_str(&gen(condition, context)?);
+            code.push_str(&truth(context)?);
+            code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", context.last_index - 1, label, label));
             code.push_str(&format!("then{}:\n", label));
-            code.push_str(&gen(node, stack, functions, last_index, last_label)?);
+            context.loops.push((loop_label.clone(), format!("begin{}", label), format!("end{}", label)));
+            code.push_str(&gen(node, context)?);
+            context.loops.pop();
             code.push_str(&format!("  br label %begin{}\n", label));
-            code.push_str(&format!("end{}:", label));
+            code.push_str(&format!("end{}:\n", label));
         },
-        Node::Operator { typ, lhs, rhs } => {
-            match typ {
-                Operator::Add => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = add i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Sub => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = sub i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Mul => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = mul i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Div => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = sdiv i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Rem => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = srem i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Power => (),
-                Operator::Root => (),
-                Operator::And => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = and i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                }
-                Operator::Xor => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = xor i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                }
-                Operator::Or => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = or i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                }
-                Operator::LShift => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = shl i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::RShift => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = ashr i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Equal => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = icmp eq i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    *last_index += 1;
-                    code.push_str(&format!("  %{} = zext i1 %{} to i64\n", last_index, *last_index - 1));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Less => {
-                    code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&gen(lhs.as_ref(), stack, functions, last_index, last_label)?);
-                    code.push_str(&format!("  %{} = icmp slt i64 %{}, %{}\n", last_index, stack.pop_back().unwrap(), stack.pop_back().unwrap()));
-                    *last_index += 1;
-                    code.push_str(&format!("  %{} = zext i1 %{} to i64\n", last_index, *last_index - 1));
-                    stack.push_back(*last_index);
-                    *last_index += 1;
-                },
-                Operator::Assign => {
-                    if let Node::Variable { offset } = lhs.as_ref() {
-                        code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                        code.push_str(&format!("  store i64 %{}, i64* %{}\n", stack.pop_back().unwrap(), offset));
-                        code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, offset));
-                        stack.push_back(*last_index);
-                        *last_index += 1;
-                    } else {
-                        return Err(format!("Not a variable"));
-                    }
-                },
-                Operator::ChangeMin => {
-                    if let Node::Variable { offset } = lhs.as_ref() {
-                        let label = *last_label;
-                        *last_label += 1;
-                        code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                        code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, offset));
-                        *last_index += 1;
-                        let ch_ptr = stack.pop_back().unwrap();
-                        code.push_str(&format!("  %{} = icmp sgt i64 %{}, %{}\n", last_index, *last_index - 1, ch_ptr));
-                        code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", last_index, label, label));
-                        *last_index += 1;
-                        code.push_str(&format!("then{}:\n", label));
-                        code.push_str(&format!("  store i64 %{}, i64* %{}\n", ch_ptr, offset));
-                        code.push_str(&format!("  br label %end{}\n", label));
-                        code.push_str(&format!("end{}:\n", label));
-                        code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, offset));
-                        stack.push_back(*last_index);
-                        *last_index += 1;
+        Node::DoWhile { label: loop_label, node, condition, span: _ } => {
+            let label = context.last_label;
+            context.last_label += 1;
+            code.push_str(&format!("  br label %then{}\n", label));
+            code.push_str(&format!("then{}:\n", label));
+            context.loops.push((loop_label.clone(), format!("begin{}", label), format!("end{}", label)));
+            code.push_str(&gen(node, context)?);
+            context.loops.pop();
+            code.push_str(&format!("  br label %begin{}\n", label));
+            code.push_str(&format!("begin{}:\n", label));
+            code.push_str(&gen(condition, context)?);
+            code.push_str(&truth(context)?);
+            code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", context.last_index - 1, label, label));
+            code.push_str(&format!("end{}:\n", label));
+        },
+        Node::Loop { label: loop_label, node, span: _ } => {
+            let label = context.last_label;
+            context.last_label += 1;
+            code.push_str(&format!("  br label %begin{}\n", label));
+            code.push_str(&format!("begin{}:\n", label));
+            context.loops.push((loop_label.clone(), format!("begin{}", label), format!("end{}", label)));
+            code.push_str(&gen(node, context)?);
+            context.loops.pop();
+            code.push_str(&format!("  br label %begin{}\n", label));
+            code.push_str(&format!("end{}:\n", label));
+        },
+        Node::Match { value, arms, default, span: _ } => {
+            let label = context.last_label;
+            context.last_label += 1;
+            code.push_str(&gen(value.as_ref(), context)?);
+            let (value, _) = context.stack.pop_back().unwrap();
+
+            // Short ranges become `switch` cases; longer ones are compared one by one on the switch's default path.
+            let mut cases = String::new();
+            let mut checks = Vec::new();
+            for (i, (patterns, _)) in arms.iter().enumerate() {
+                for (lo, hi) in patterns {
+                    if (*hi as i128) - (*lo as i128) < 16 {
+                        for case in *lo..=*hi {
+                            cases.push_str(&format!(" i64 {}, label %case{}.{}", case, label, i));
+                        }
                     } else {
-                        return Err(format!("Not a variable"));
+                        checks.push((*lo, *hi, i));
                     }
+                }
+            }
+            let fallback = if checks.is_empty() { format!("else{}", label) } else { format!("range{}.0", label) };
+            code.push_str(&format!("  switch i64 %{}, label %{} [{} ]\n", value, fallback, cases));
+            for (k, (lo, hi, i)) in checks.iter().enumerate() {
+                let next = if k + 1 < checks.len() { format!("range{}.{}", label, k + 1) } else { format!("else{}", label) };
+                code.push_str(&format!("range{}.{}:\n", label, k));
+                code.push_str(&format!("  %{} = icmp sge i64 %{}, {}\n", context.last_index, value, lo));
+                code.push_str(&format!("  %{} = icmp sle i64 %{}, {}\n", context.last_index + 1, value, hi));
+                code.push_str(&format!("  %{} = and i1 %{}, %{}\n", context.last_index + 2, context.last_index, context.last_index + 1));
+                code.push_str(&format!("  br i1 %{}, label %case{}.{}, label %{}\n", context.last_index + 2, label, i, next));
+                context.last_index += 3;
+            }
+
+            for (i, (_, statement)) in arms.iter().enumerate() {
+                code.push_str(&format!("case{}.{}:\n", label, i));
+                code.push_str(&gen(statement, context)?);
+                code.push_str(&format!("  br label %end{}\n", label));
+            }
+            code.push_str(&format!("else{}:\n", label));
+            if let Some(default) = default.as_ref() {
+                code.push_str(&gen(default, context)?);
+            }
+            code.push_str(&format!("  br label %end{}\n", label));
+            code.push_str(&format!("end{}:\n", label));
+        },
+        Node::Break { label, span: _ } => {
+            code.push_str(&format!("  br label %{}\n", find_loop(&context.loops, label)?.2));
+            context.last_index += 1;
+        },
+        Node::Continue { label, span: _ } => {
+            code.push_str(&format!("  br label %{}\n", find_loop(&context.loops, label)?.1));
+            context.last_index += 1;
+        },
+        Node::Operator { typ, lhs, rhs, span } => {
+            match typ {
+                Operator::Assign => {
+                    code.push_str(&gen(rhs.as_ref(), context)?);
+                    let value = context.stack.pop_back().unwrap();
+                    let (pointer_code, pointer, typ) = address(lhs, context)?;
+                    code.push_str(&pointer_code);
+                    let (convert_code, value) = convert(value, typ, &mut context.last_index)?;
+                    code.push_str(&convert_code);
+                    code.push_str(&format!("  store {} %{}, {}* {}\n", llvm_type(typ), value, llvm_type(typ), pointer));
+                    context.stack.push_back((value, typ));
                 },
-                Operator::ChangeMax => {
-                    if let Node::Variable { offset } = lhs.as_ref() {
-                        let label = *last_label;
-                        *last_label += 1;
-                        code.push_str(&gen(rhs.as_ref(), stack, functions, last_index, last_label)?);
-                        code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, offset));
-                        *last_index += 1;
-                        let ch_ptr = stack.pop_back().unwrap();
-                        code.push_str(&format!("  %{} = icmp slt i64 %{}, %{}\n", last_index, *last_index - 1, ch_ptr));
-                        code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", last_index, label, label));
-                        *last_index += 1;
-                        code.push_str(&format!("then{}:\n", label));
-                        code.push_str(&format!("  store i64 %{}, i64* %{}\n", ch_ptr, offset));
-                        code.push_str(&format!("  br label %end{}\n", label));
-                        code.push_str(&format!("end{}:\n", label));
-                        code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, offset));
-                        stack.push_back(*last_index);
-                        *last_index += 1;
-                    } else {
-                        return Err(format!("Not a variable"));
-                    }
+                Operator::ChangeMin | Operator::ChangeMax => {
+                    let change_min = matches!(typ, Operator::ChangeMin);
+                    let label = context.last_label;
+                    context.last_label += 1;
+                    code.push_str(&gen(rhs.as_ref(), context)?);
+                    let value = context.stack.pop_back().unwrap();
+                    let (pointer_code, pointer, typ) = address(lhs, context)?;
+                    code.push_str(&pointer_code);
+                    let (convert_code, ch_ptr) = convert(value, typ, &mut context.last_index)?;
+                    code.push_str(&convert_code);
+                    code.push_str(&format!("  %{} = load {}, {}* {}\n", context.last_index, llvm_type(typ), llvm_type(typ), pointer));
+                    context.last_index += 1;
+                    let (less, greater) = if change_min { (ch_ptr, context.last_index - 1) } else { (context.last_index - 1, ch_ptr) };
+                    code.push_str(&format!("  %{} = {} {} %{}, %{}\n", context.last_index, compare_instruction(typ, "slt", "olt"), llvm_type(typ), less, greater));
+                    code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", context.last_index, label, label));
+                    context.last_index += 1;
+                    code.push_str(&format!("then{}:\n", label));
+                    code.push_str(&format!("  store {} %{}, {}* {}\n", llvm_type(typ), ch_ptr, llvm_type(typ), pointer));
+                    code.push_str(&format!("  br label %end{}\n", label));
+                    code.push_str(&format!("end{}:\n", label));
+                    code.push_str(&format!("  %{} = load {}, {}* {}\n", context.last_index, llvm_type(typ), llvm_type(typ), pointer));
+                    context.stack.push_back((context.last_index, typ));
+                    context.last_index += 1;
                 },
                 Operator::Exchange => {
-                    if let Node::Variable { offset: ol } = lhs.as_ref() {
-                        if let Node::Variable { offset: or } = rhs.as_ref() {
-                            code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, ol));
-                            *last_index += 1;
-                            code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, or));
-                            code.push_str(&format!("  store i64 %{}, i64* %{}\n", *last_index - 1, or));
-                            code.push_str(&format!("  store i64 %{}, i64* %{}\n", last_index, ol));
-                            *last_index += 1;
-                            code.push_str(&format!("  %{} = load i64, i64* %{}\n", *last_index, ol));
-                            stack.push_back(*last_index);
-                            *last_index += 1;
-                        } else {
-                            return Err(format!("Not a variable"));
-                        }
-                    } else {
-                        return Err(format!("Not a variable"));
+                    let (lhs_code, lhs_pointer, typ) = address(lhs, context)?;
+                    code.push_str(&lhs_code);
+                    let (rhs_code, rhs_pointer, rhs_type) = address(rhs, context)?;
+                    code.push_str(&rhs_code);
+                    if rhs_type != typ {
+                        return Err(CompileError::new(ErrorKind::Internal, "Type mismatch", span.clone()));
                     }
+                    code.push_str(&format!("  %{} = load {}, {}* {}\n", context.last_index, llvm_type(typ), llvm_type(typ), lhs_pointer));
+                    context.last_index += 1;
+                    code.push_str(&format!("  %{} = load {}, {}* {}\n", context.last_index, llvm_type(typ), llvm_type(typ), rhs_pointer));
+                    code.push_str(&format!("  store {} %{}, {}* {}\n", llvm_type(typ), context.last_index - 1, llvm_type(typ), rhs_pointer));
+                    code.push_str(&format!("  store {} %{}, {}* {}\n", llvm_type(typ), context.last_index, llvm_type(typ), lhs_pointer));
+                    context.stack.push_back((context.last_index, typ));
+                    context.last_index += 1;
+                },
+                _ => {
+                    code.push_str(&gen(rhs.as_ref(), context)?);
+                    code.push_str(&gen(lhs.as_ref(), context)?);
+                    code.push_str(&operation(typ, context)?);
                 },
             }
         },
-        Node::Variable { offset } => {
-            code.push_str(&format!("  %{} = load i64, i64* %{}\n", last_index, offset));
-            stack.push_back(*last_index);
-            *last_index += 1;
+        Node::CompoundAssign { op, target, value, span: _ } => {
+            // The target's address is computed once and used for both the load and the store.
+            code.push_str(&gen(value.as_ref(), context)?);
+            let (pointer_code, pointer, typ) = address(target, context)?;
+            code.push_str(&pointer_code);
+            code.push_str(&format!("  %{} = load {}, {}* {}\n", context.last_index, llvm_type(typ), llvm_type(typ), pointer));
+            context.stack.push_back((context.last_index, typ));
+            context.last_index += 1;
+            code.push_str(&operation(op, context)?);
+            let result = context.stack.pop_back().unwrap();
+            let (convert_code, result) = convert(result, typ, &mut context.last_index)?;
+            code.push_str(&convert_code);
+            code.push_str(&format!("  store {} %{}, {}* {}\n", llvm_type(typ), result, llvm_type(typ), pointer));
+            context.stack.push_back((result, typ));
         },
-        Node::FuncCall { function_name, arguments } => {
+        Node::Increment { op, prefix, target, span: _ } => {
+            let (pointer_code, pointer, typ) = address(target, context)?;
+            code.push_str(&pointer_code);
+            code.push_str(&format!("  %{} = load {}, {}* {}\n", context.last_index, llvm_type(typ), llvm_type(typ), pointer));
+            context.last_index += 1;
+            let (instruction, one) = match (op, typ) {
+                (Operator::Add, ValueType::Float) => ("fadd", "1.0"),
+                (_, ValueType::Float) => ("fsub", "1.0"),
+                (Operator::Add, _) => ("add", "1"),
+                _ => ("sub", "1"),
+            };
+            code.push_str(&format!("  %{} = {} {} %{}, {}\n", context.last_index, instruction, llvm_type(typ), context.last_index - 1, one));
+            code.push_str(&format!("  store {} %{}, {}* {}\n", llvm_type(typ), context.last_index, llvm_type(typ), pointer));
+            context.stack.push_back((if *prefix { context.last_index } else { context.last_index - 1 }, typ));
+            context.last_index += 1;
+        },
+        Node::Conditional { condition, true_case, false_case, span: _ } => {
+            let label = context.last_label;
+            context.last_label += 1;
+            code.push_str(&gen(condition.as_ref(), context)?);
+            code.push_str(&truth(context)?);
+            let condition = context.last_index - 1;
+            if is_pure(true_case) && is_pure(false_case) {
+                code.push_str(&gen(true_case.as_ref(), context)?);
+                code.push_str(&gen(false_case.as_ref(), context)?);
+                let false_value = context.stack.pop_back().unwrap();
+                let true_value = context.stack.pop_back().unwrap();
+                let typ = if true_value.1 == false_value.1 { true_value.1 } else { ValueType::Float };
+                let (true_code, true_value) = convert(true_value, typ, &mut context.last_index)?;
+                code.push_str(&true_code);
+                let (false_code, false_value) = convert(false_value, typ, &mut context.last_index)?;
+                code.push_str(&false_code);
+                code.push_str(&format!("  %{} = select i1 %{}, {} %{}, {} %{}\n", context.last_index, condition, llvm_type(typ), true_value, llvm_type(typ), false_value));
+                context.stack.push_back((context.last_index, typ));
+                context.last_index += 1;
+            } else {
+                // Each arm leaves through its own block (`next` and `begin`), so the phi knows where the values come from.
+                // An int arm is widened there with a named value, as the other arm's type is only known afterwards.
+                code.push_str(&format!("  br i1 %{}, label %then{}, label %else{}\n", condition, label, label));
+                code.push_str(&format!("then{}:\n", label));
+                let true_code = gen(true_case.as_ref(), context)?;
+                let true_value = context.stack.pop_back().unwrap();
+                let false_code = gen(false_case.as_ref(), context)?;
+                let false_value = context.stack.pop_back().unwrap();
+                let typ = if true_value.1 == false_value.1 { true_value.1 } else { ValueType::Float };
+                code.push_str(&true_code);
+                code.push_str(&format!("  br label %next{}\n", label));
+                code.push_str(&format!("next{}:\n", label));
+                let true_value = widen(true_value, typ, &format!("then{}.float", label), &mut code)?;
+                code.push_str(&format!("  br label %end{}\n", label));
+                code.push_str(&format!("else{}:\n", label));
+                code.push_str(&false_code);
+                code.push_str(&format!("  br label %begin{}\n", label));
+                code.push_str(&format!("begin{}:\n", label));
+                let false_value = widen(false_value, typ, &format!("else{}.float", label), &mut code)?;
+                code.push_str(&format!("  br label %end{}\n", label));
+                code.push_str(&format!("end{}:\n", label));
+                code.push_str(&format!("  %{} = phi {} [ {}, %next{} ], [ {}, %begin{} ]\n", context.last_index, llvm_type(typ), true_value, label, false_value, label));
+                context.stack.push_back((context.last_index, typ));
+                context.last_index += 1;
+            }
+        },
+        Node::Logical { typ, lhs, rhs, span: _ } => {
+            // The rhs only runs when the lhs does not decide the result; `begin` and `else` are the two blocks reaching the phi.
+            let label = context.last_label;
+            context.last_label += 1;
+            code.push_str(&gen(lhs.as_ref(), context)?);
+            code.push_str(&truth(context)?);
+            let lhs = context.last_index - 1;
+            code.push_str(&format!("  br label %begin{}\n", label));
+            code.push_str(&format!("begin{}:\n", label));
+            let decided = match typ {
+                Logical::And => {
+                    code.push_str(&format!("  br i1 %{}, label %then{}, label %end{}\n", lhs, label, label));
+                    0
+                },
+                Logical::Or => {
+                    code.push_str(&format!("  br i1 %{}, label %end{}, label %then{}\n", lhs, label, label));
+                    1
+                },
+            };
+            code.push_str(&format!("then{}:\n", label));
+            code.push_str(&gen(rhs.as_ref(), context)?);
+            code.push_str(&truth(context)?);
+            let rhs = context.last_index - 1;
+            code.push_str(&format!("  br label %else{}\n", label));
+            code.push_str(&format!("else{}:\n", label));
+            code.push_str(&format!("  br label %end{}\n", label));
+            code.push_str(&format!("end{}:\n", label));
+            code.push_str(&format!("  %{} = phi i1 [ {}, %begin{} ], [ %{}, %else{} ]\n", context.last_index, decided, label, rhs, label));
+            context.last_index += 1;
+            code.push_str(&format!("  %{} = zext i1 %{} to i64\n", context.last_index, context.last_index - 1));
+            context.stack.push_back((context.last_index, ValueType::Int));
+            context.last_index += 1;
+        },
+        Node::Variable { offset, span } => {
+            let typ = context.variable_types[*offset].ok_or_else(|| CompileError::new(ErrorKind::Internal, "Undeclared variable", span.clone()))?;
+            code.push_str(&format!("  %{} = load {}, {}* %{}\n", context.last_index, llvm_type(typ), llvm_type(typ), offset));
+            context.stack.push_back((context.last_index, typ));
+            context.last_index += 1;
+        },
+        Node::GlobalVariable { index, span: _ } => {
+            let (name, typ) = &context.globals[*index];
+            code.push_str(&format!("  %{} = load {}, {}* @global.{}\n", context.last_index, llvm_type(*typ), llvm_type(*typ), name));
+            context.stack.push_back((context.last_index, *typ));
+            context.last_index += 1;
+        },
+        Node::Index { array, index, span: _ } => {
+            let (pointer_code, pointer, typ) = element_pointer(array, index, context)?;
+            code.push_str(&pointer_code);
+            code.push_str(&format!("  %{} = load {}, {}* {}\n", context.last_index, llvm_type(typ), llvm_type(typ), pointer));
+            context.stack.push_back((context.last_index, typ));
+            context.last_index += 1;
+        },
+        Node::Array { typ, len, span: _ } => {
+            // The storage is allocated once in the entry block and cleared every time the expression runs.
+            let element = typ.element().unwrap();
+            let array = context.arrays.len();
+            context.arrays.push((element, *len));
+            code.push_str(&format!("  %{} = bitcast [{} x {}]* %array{} to i8*\n", context.last_index, len, llvm_type(element), array));
+            code.push_str(&format!("  call void @llvm.memset.p0i8.i64(i8* %{}, i8 0, i64 {}, i1 false)\n", context.last_index, len * 8));
+            context.last_index += 1;
+            code.push_str(&format!("  %{} = getelementptr [{} x {}], [{} x {}]* %array{}, i64 0, i64 0\n", context.last_index, len, llvm_type(element), len, llvm_type(element), array));
+            context.stack.push_back((context.last_index, *typ));
+            context.last_index += 1;
+        },
+        Node::FuncCall { function_name, arguments, span } => {
             let mut found = false;
-            for (name, args_num) in functions {
-                if name == function_name && *args_num == arguments.len() {
+            let functions = context.functions;
+            for (name, arg_types, return_type) in functions {
+                if name == function_name && arg_types.len() == arguments.len() {
                     found = true;
 
                     let mut args = Vec::new();
                     for arg in arguments {
-                        code.push_str(&gen(arg, stack, functions, last_index, last_label)?);
-                        args.push(stack.pop_back().unwrap());
+                        code.push_str(&gen(arg, context)?);
+                        args.push(context.stack.pop_back().unwrap());
                     }
-                    code.push_str(&format!("  %{} = call i64 @{}(", last_index, function_name));
+                    // `debug` and `print` take any value, through an overload for each type.
+                    let function_name = if (function_name == "debug" || function_name == "print") && args[0].1 != ValueType::Int {
+                        format!("{}.{}", function_name, if args[0].1 == ValueType::Float { "float" } else { "str" })
+                    } else {
+                        for (arg, typ) in args.iter_mut().zip(arg_types) {
+                            let (convert_code, value) = convert(*arg, *typ, &mut context.last_index)?;
+                            code.push_str(&convert_code);
+                            *arg = (value, *typ);
+                        }
+                        function_name.clone()
+                    };
+                    code.push_str(&format!("  %{} = call {} @{}(", context.last_index, llvm_type(*return_type), function_name));
                     let mut first = true;
-                    for i in args {
+                    for (i, typ) in args {
                         if !first {
                             code.push_str(", ");
                         }
                         first = false;
-                        code.push_str(&format!("i64 %{}", i));
+                        code.push_str(&format!("{} %{}", llvm_type(typ), i));
                     }
                     code.push_str(")\n");
-                    stack.push_back(*last_index);
-                    *last_index += 1;
+                    context.stack.push_back((context.last_index, *return_type));
+                    context.last_index += 1;
 
                     break;
                 }
             }
             if !found {
-                return Err(format!("Function not found"));
+                return Err(CompileError::new(ErrorKind::Internal, "Function not found", span.clone()));
+            }
+        },
+        Node::Cast { typ, node, span } => {
+            code.push_str(&gen(node.as_ref(), context)?);
+            let (value, from) = context.stack.pop_back().unwrap();
+            match (from, typ) {
+                (ValueType::Int, ValueType::Float) => {
+                    code.push_str(&format!("  %{} = sitofp i64 %{} to double\n", context.last_index, value));
+                    context.stack.push_back((context.last_index, ValueType::Float));
+                    context.last_index += 1;
+                },
+                (ValueType::Float, ValueType::Int) => {
+                    code.push_str(&format!("  %{} = fptosi double %{} to i64\n", context.last_index, value));
+                    context.stack.push_back((context.last_index, ValueType::Int));
+                    context.last_index += 1;
+                },
+                (ValueType::Str, _) | (_, ValueType::Str) if from != *typ => return Err(CompileError::new(ErrorKind::Internal, "Type mismatch", span.clone())),
+                _ => context.stack.push_back((value, from)),
             }
         },
-        Node::Number { num } => {
-            code.push_str(&format!("  %{} = add i64 {}, 0\n", last_index, num));
-            stack.push_back(*last_index);
+        Node::Number { num, span: _ } => {
+            code.push_str(&format!("  %{} = add i64 {}, 0\n", context.last_index, num));
+            context.stack.push_back((context.last_index, ValueType::Int));
+            context.last_index += 1;
+        },
+        Node::Float { num, span: _ } => {
+            code.push_str(&format!("  %{} = fadd double 0x{:016X}, 0.0\n", context.last_index, num.to_bits()));
+            context.stack.push_back((context.last_index, ValueType::Float));
+            context.last_index += 1;
+        },
+        Node::Str { text, span: _ } => {
+            let index = string_index(context.strings, text);
+            code.push_str(&format!("  %{} = getelementptr [{} x i8], [{} x i8]* @str.{}, i32 0, i32 0\n", context.last_index, text.len() + 1, text.len() + 1, index));
+            context.stack.push_back((context.last_index, ValueType::Str));
+            context.last_index += 1;
+        },
+    }
+
+    Ok(code)
+}
+
+fn llvm_type(typ: ValueType) -> &'static str {
+    match typ {
+        ValueType::Int => "i64",
+        ValueType::Float => "double",
+        ValueType::Str => "i8*",
+        ValueType::IntArray => "i64*",
+        ValueType::FloatArray => "double*",
+    }
+}
+
+// The slot an assignment writes to, with its type.
+fn address(node: &Node, context: &mut Context) -> Result<(String, String, ValueType), CompileError> {
+    match node {
+        Node::Variable { offset, span } => Ok((String::new(), format!("%{}", offset), context.variable_types[*offset].ok_or_else(|| CompileError::new(ErrorKind::Internal, "Undeclared variable", span.clone()))?)),
+        Node::GlobalVariable { index, span: _ } => Ok((String::new(), format!("@global.{}", context.globals[*index].0), context.globals[*index].1)),
+        Node::Index { array, index, span: _ } => element_pointer(array, index, context),
+        _ => Err(CompileError::new(ErrorKind::Internal, "Not assignable", node.span().clone())),
+    }
+}
+
+// Evaluates the array and then the index, returning a pointer to the element and the element type.
+fn element_pointer(array: &Node, index: &Node, context: &mut Context) -> Result<(String, String, ValueType), CompileError> {
+    let mut code = gen(array, context)?;
+    code.push_str(&gen(index, context)?);
+    let (index, _) = context.stack.pop_back().unwrap();
+    let element = context.stack.back().unwrap().1.element().ok_or_else(|| CompileError::new(ErrorKind::Internal, "Not an array", array.span().clone()))?;
+    let (array, _) = context.stack.pop_back().unwrap();
+
+    code.push_str(&format!("  %{} = getelementptr {}, {}* %{}, i64 %{}\n", context.last_index, llvm_type(element), llvm_type(element), array, index));
+    context.last_index += 1;
+
+    Ok((code, format!("%{}", context.last_index - 1), element))
+}
+
+fn compare_instruction(typ: ValueType, int_condition: &str, float_condition: &str) -> String {
+    match typ {
+        ValueType::Float => format!("fcmp {}", float_condition),
+        _ => format!("icmp {}", int_condition),
+    }
+}
+
+// Widens an int to a float; narrowing has to be spelled out with `int(...)`.
+fn convert(value: (usize, ValueType), typ: ValueType, last_index: &mut usize) -> Result<(String, usize), CompileError> {
+    match (value.1, typ) {
+        (ValueType::Int, ValueType::Float) => {
+            let code = format!("  %{} = sitofp i64 %{} to double\n", last_index, value.0);
             *last_index += 1;
+            Ok((code, *last_index - 1))
         },
+        (from, to) if from != to => Err(CompileError::internal("Type mismatch")),
+        _ => Ok((String::new(), value.0)),
     }
+}
+
+// Like `convert`, but into a named value, for code whose numbering is already fixed; returns the operand.
+fn widen(value: (usize, ValueType), typ: ValueType, name: &str, code: &mut String) -> Result<String, CompileError> {
+    match (value.1, typ) {
+        (ValueType::Int, ValueType::Float) => {
+            code.push_str(&format!("  %{} = sitofp i64 %{} to double\n", name, value.0));
+            Ok(format!("%{}", name))
+        },
+        (from, to) if from != to => Err(CompileError::internal("Type mismatch")),
+        _ => Ok(format!("%{}", value.0)),
+    }
+}
+
+// Whether a node can be evaluated even when its branch is not taken: no side effects, nothing that traps and no control flow.
+fn is_pure(node: &Node) -> bool {
+    match node {
+        Node::Number { num: _, span: _ } | Node::Float { num: _, span: _ } | Node::Str { text: _, span: _ } | Node::Variable { offset: _, span: _ } | Node::GlobalVariable { index: _, span: _ } => true,
+        Node::Cast { typ: _, node, span: _ } => is_pure(node),
+        Node::Operator { typ, lhs, rhs, span: _ } => matches!(typ, Operator::Add | Operator::Sub | Operator::Mul | Operator::And | Operator::Xor | Operator::Or | Operator::LShift | Operator::RShift | Operator::Equal | Operator::Less) && is_pure(lhs) && is_pure(rhs),
+        _ => false,
+    }
+}
+
+// Pops the lhs and then the rhs, widening both to float if either of them is one.
+fn operands(context: &mut Context) -> Result<(String, usize, usize, ValueType), CompileError> {
+    let lhs = context.stack.pop_back().unwrap();
+    let rhs = context.stack.pop_back().unwrap();
+    let typ = match (lhs.1, rhs.1) {
+        (typ, _) | (_, typ) if !typ.is_number() => return Err(CompileError::internal("Type mismatch")),
+        (ValueType::Float, _) | (_, ValueType::Float) => ValueType::Float,
+        _ => ValueType::Int,
+    };
+
+    let (mut code, lhs) = convert(lhs, typ, &mut context.last_index)?;
+    let (rhs_code, rhs) = convert(rhs, typ, &mut context.last_index)?;
+    code.push_str(&rhs_code);
+
+    Ok((code, lhs, rhs, typ))
+}
+
+// Applies an arithmetic, bitwise or comparison operator to the lhs on top of the stack and the rhs below it.
+fn operation(typ: &Operator, context: &mut Context) -> Result<String, CompileError> {
+    match typ {
+        Operator::Add => binary(context, "add", Some("fadd")),
+        Operator::Sub => binary(context, "sub", Some("fsub")),
+        Operator::Mul => binary(context, "mul", Some("fmul")),
+        Operator::Div => binary(context, "sdiv", Some("fdiv")),
+        Operator::Rem => binary(context, "srem", Some("frem")),
+        Operator::Power => builtin(context, "builtin.power", "llvm.pow.f64"),
+        Operator::Root => builtin(context, "builtin.root", "builtin.root.float"),
+        Operator::And => binary(context, "and", None),
+        Operator::Xor => binary(context, "xor", None),
+        Operator::Or => binary(context, "or", None),
+        Operator::LShift => binary(context, "shl", None),
+        Operator::RShift => binary(context, "ashr", None),
+        Operator::Equal => compare(context, "eq", "oeq"),
+        Operator::Less => compare(context, "slt", "olt"),
+        _ => Err(CompileError::internal("Not an operation")),
+    }
+}
+
+fn binary(context: &mut Context, int_instruction: &str, float_instruction: Option<&str>) -> Result<String, CompileError> {
+    let (mut code, lhs, rhs, typ) = operands(context)?;
+    let instruction = match typ {
+        ValueType::Float => float_instruction.ok_or_else(|| CompileError::internal("Type mismatch"))?,
+        _ => int_instruction,
+    };
+
+    code.push_str(&format!("  %{} = {} {} %{}, %{}\n", context.last_index, instruction, llvm_type(typ), lhs, rhs));
+    context.stack.push_back((context.last_index, typ));
+    context.last_index += 1;
+
+    Ok(code)
+}
+
+fn compare(context: &mut Context, int_condition: &str, float_condition: &str) -> Result<String, CompileError> {
+    let (mut code, lhs, rhs, typ) = operands(context)?;
+
+    code.push_str(&format!("  %{} = {} {} %{}, %{}\n", context.last_index, compare_instruction(typ, int_condition, float_condition), llvm_type(typ), lhs, rhs));
+    context.last_index += 1;
+    code.push_str(&format!("  %{} = zext i1 %{} to i64\n", context.last_index, context.last_index - 1));
+    context.stack.push_back((context.last_index, ValueType::Int));
+    context.last_index += 1;
+
+    Ok(code)
+}
+
+fn builtin(context: &mut Context, int_function: &str, float_function: &str) -> Result<String, CompileError> {
+    let (mut code, lhs, rhs, typ) = operands(context)?;
+    let function = match typ {
+        ValueType::Float => float_function,
+        _ => int_function,
+    };
+
+    code.push_str(&format!("  %{} = call {} @{}({} %{}, {} %{})\n", context.last_index, llvm_type(typ), function, llvm_type(typ), lhs, llvm_type(typ), rhs));
+    context.stack.push_back((context.last_index, typ));
+    context.last_index += 1;
+
+    Ok(code)
+}
+
+// Pops a value and leaves whether it is nonzero as an i1 in `%{last_index - 1}`.
+fn truth(context: &mut Context) -> Result<String, CompileError> {
+    let (value, typ) = context.stack.pop_back().unwrap();
+    let code = match typ {
+        ValueType::Int => format!("  %{} = icmp ne i64 %{}, 0\n", context.last_index, value),
+        ValueType::Float => format!("  %{} = fcmp une double %{}, 0.0\n", context.last_index, value),
+        _ => return Err(CompileError::internal("Type mismatch")),
+    };
+    context.last_index += 1;
 
     Ok(code)
-}
\ No newline at end of file
+}
+
+// The `@str.{index}` constant holding the text, added the first time it is used.
+fn string_index(strings: &mut Vec<Vec<u8>>, text: &[u8]) -> usize {
+    if let Some(index) = strings.iter().position(|string| string == text) {
+        index
+    } else {
+        strings.push(text.to_vec());
+        strings.len() - 1
+    }
+}
+
+// Printable ASCII other than `"` and `\` is kept, everything else becomes a `\XX` escape.
+fn escape(string: &[u8]) -> String {
+    let mut escaped = String::new();
+    for byte in string {
+        if (0x20..0x7F).contains(byte) && *byte != b'"' && *byte != b'\\' {
+            escaped.push(*byte as char);
+        } else {
+            escaped.push_str(&format!("\\{:02X}", byte));
+        }
+    }
+    escaped.push_str("\\00");
+
+    escaped
+}
+
+fn find_loop<'a>(loops: &'a [(Option<String>, String, String)], label: &Option<String>) -> Result<&'a (Option<String>, String, String), CompileError> {
+    let found = if label.is_some() {
+        loops.iter().rev().find(|(loop_label, _, _)| loop_label == label)
+    } else {
+        loops.last()
+    };
+
+    if let Some(found) = found {
+        Ok(found)
+    } else {
+        Err(CompileError::internal("Loop not found"))
+    }
+}
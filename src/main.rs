@@ -1,45 +1,43 @@
 mod env_args;
 mod file_reader;
-mod tokenizer;
-mod parser;
-mod llvm_generator;
 mod file_writer;
 
 use std::env;
+use std::io::{self, IsTerminal};
+use std::process;
+use maple_lang::compile;
+use maple_lang::compile_error::CompileError;
 
 fn main() {
     let args = env::args().collect::<Vec<String>>();
+    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
 
-    let error = env_args::check_args(&args);
-    if let Some(e) = error {
-        println!("Error occurred: {}", e);
-        return;
+    if let Err(error) = env_args::check_args(&args) {
+        abort(vec![error], "", color);
     }
 
     let src_path = args[1].clone();
-    let src = file_reader::read(src_path);
-
-    let tokens = tokenizer::tokenize(&src);
-    if let Err(message) = &tokens {
-        println!("Error occurred: {}", message);
-        return;
-    }
-    let tokens = tokens.unwrap();
-
-    let program = parser::parse(tokens);
-    if let Err(message) = &program {
-        println!("Error occurred: {}", message);
-        return;
+    let src = file_reader::read(&src_path).unwrap_or_else(|error| abort(vec![error], "", color));
+
+    match compile(&src) {
+        Ok(llvm) => {
+            let res_path = args[2].clone();
+            if let Err(error) = file_writer::write(res_path, llvm) {
+                abort(vec![error], "", color);
+            }
+        },
+        Err(errors) => abort(errors.into_iter().map(|error| error.in_file(&src_path)).collect(), &src, color),
     }
-    let program = program.unwrap();
+}
 
-    let llvm = llvm_generator::generate(program);
-    if let Err(message) = &llvm {
-        println!("Error occurred: {}", message);
-        return;
+// Renders every error to stderr, quoting `src` where they point into it, and exits with status 1.
+fn abort(errors: Vec<CompileError>, src: &str, color: bool) -> ! {
+    for (i, error) in errors.iter().enumerate() {
+        if i > 0 {
+            eprintln!();
+        }
+        eprint!("{}", error.render(src, color));
     }
-    let llvm = llvm.unwrap();
-
-    let res_path = args[2].clone();
-    file_writer::write(res_path, llvm);
+    eprintln!("\nerror: aborting due to {} previous error{}", errors.len(), if errors.len() == 1 { "" } else { "s" });
+    process::exit(1);
 }
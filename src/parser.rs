@@ -1,37 +1,111 @@
+pub mod constant;
 pub mod node;
+pub mod scope;
 
+use crate::compile_error::{CompileError, ErrorKind, Span};
+use crate::parser::node::logical::Logical;
 use crate::parser::node::operator::Operator;
 use crate::parser::node::Node;
+use crate::parser::node::value_type::ValueType;
+use crate::parser::scope::Scope;
 use crate::tokenizer::token::Token;
 use crate::tokenizer::token::token_type::symbol::Symbol;
 use crate::tokenizer::token::token_type::TokenType;
 use crate::tokenizer::token::token_type::word::Word;
 
-pub fn parse(tokens: Vec<Token>) -> Result<Node, String> {
-    program(&tokens)
+// Every syntax error is collected rather than just the first; the program comes back regardless, without the statements and items that failed to parse.
+pub fn parse(tokens: Vec<Token>) -> (Node, Vec<CompileError>) {
+    let mut errors = Vec::new();
+    let program = program(&tokens, &mut errors);
+
+    (program, errors)
 }
 
-fn program(tokens: &Vec<Token>) -> Result<Node, String> {
+fn program(tokens: &Vec<Token>, errors: &mut Vec<CompileError>) -> Node {
     let mut pos = 0;
 
+    let mut scope = Scope::default();
+    let mut globals = Vec::new();
     let mut functions = Vec::new();
 
     while tokens[pos].typ != TokenType::Eof {
-        let function = function(tokens, &mut pos)?;
+        let first = pos;
+        let item = if tokens[pos].typ == TokenType::Word(Word::Let) || tokens[pos].typ == TokenType::Word(Word::Const) {
+            global(tokens, &mut pos, &mut scope, &mut globals)
+        } else {
+            function(tokens, &mut pos, &scope, errors).map(|function| functions.push(function))
+        };
+
+        if let Err(error) = item {
+            errors.push(error);
+            // The item's own `fn` is skipped so that it does not stop the search.
+            synchronize(tokens, &mut pos, first + 1);
+        }
+    }
+
+    Node::Program { globals, functions, span: span(tokens, 0, pos) }
+}
+
+// A top-level `let` or `const`, whose initializer is evaluated here; a const is then replaced by its value wherever it is used.
+fn global(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope, globals: &mut Vec<Node>) -> Result<(), CompileError> {
+    let first = *pos;
+    let constant = tokens[*pos].typ == TokenType::Word(Word::Const);
+    *pos += 1;
+
+    let (name, typ, value) = declaration(tokens, pos, scope)?;
+
+    if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
+        *pos += 1;
+    } else {
+        return Err(expected(tokens, *pos, "`;`"));
+    }
+
+    if scope.global(&name).is_some() {
+        return Err(CompileError::new(ErrorKind::DuplicateGlobal, format!("duplicate global `{}`", name), tokens[first + 1].span()));
+    }
+
+    // A global array gets its own zeroed storage, but a const cannot be one.
+    let value = match value {
+        Node::Array { typ: _, len: _, span: _ } if !constant => value,
+        _ => constant::evaluate(&value)?,
+    };
+    let value = match (value, typ) {
+        (Node::Number { num, span }, Some(ValueType::Float)) => Node::Float { num: num as f64, span },
+        (value, _) => value,
+    };
+
+    let found = match &value {
+        Node::Number { num: _, span: _ } => ValueType::Int,
+        Node::Float { num: _, span: _ } => ValueType::Float,
+        Node::Str { text: _, span: _ } => ValueType::Str,
+        Node::Array { typ, len: _, span: _ } => *typ,
+        _ => return Err(CompileError::new(ErrorKind::NotConstant, "not a constant expression", value.span().clone())),
+    };
+    let typ = typ.unwrap_or(found);
+    if typ != found {
+        return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: expected {}, found {}", typ.to_str(), found.to_str()), value.span().clone()));
+    }
 
-        functions.push(function);
+    if constant {
+        scope.globals.push((name, value));
+    } else {
+        scope.globals.push((name.clone(), Node::GlobalVariable { index: globals.len(), span: tokens[first + 1].span() }));
+        globals.push(Node::Global { name, typ, value: Box::new(value), span: span(tokens, first, *pos) });
     }
 
-    Ok(Node::Program { functions })
+    Ok(())
 }
 
-fn function(tokens: &Vec<Token>, pos: &mut usize) -> Result<Node, String> {
+fn function(tokens: &Vec<Token>, pos: &mut usize, globals: &Scope, errors: &mut Vec<CompileError>) -> Result<Node, CompileError> {
+    let first = *pos;
+
     if tokens[*pos].typ == TokenType::Word(Word::Function) {
         *pos += 1;
         if let TokenType::Ident(function_name) = &tokens[*pos].typ {
             *pos += 1;
 
-            let mut variables = Vec::new();
+            let mut scope = globals.function_scope();
+            let mut arg_types = Vec::new();
 
             if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenSquare) {
                 *pos += 1;
@@ -42,7 +116,7 @@ fn function(tokens: &Vec<Token>, pos: &mut usize) -> Result<Node, String> {
                         if tokens[*pos].typ == TokenType::Symbol(Symbol::Comma) {
                             *pos += 1;
                         } else {
-                            return Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos));
+                            return Err(expected(tokens, *pos, "`,` or `]`"));
                         }
                     }
                     first = false;
@@ -50,304 +124,755 @@ fn function(tokens: &Vec<Token>, pos: &mut usize) -> Result<Node, String> {
                     if let TokenType::Ident(argument_name) = &tokens[*pos].typ {
                         *pos += 1;
 
-                        variables.push(argument_name.clone());
+                        scope.declare(argument_name);
                     } else {
-                        return Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos));
+                        return Err(expected(tokens, *pos, "an identifier"));
                     }
+
+                    arg_types.push(if tokens[*pos].typ == TokenType::Symbol(Symbol::Colon) {
+                        *pos += 1;
+
+                        value_type(tokens, pos)?
+                    } else {
+                        ValueType::Int
+                    });
                 }
                 *pos += 1;
             }
 
-            let args_num = variables.len();
+            let return_type = if tokens[*pos].typ == TokenType::Symbol(Symbol::Arrow) {
+                *pos += 1;
+
+                let first = *pos;
+                let typ = value_type(tokens, pos)?;
+                // Arrays live in their function's stack frame, so they cannot outlive it.
+                if typ.element().is_some() {
+                    return Err(CompileError::new(ErrorKind::ArrayReturned, "arrays cannot be returned", span(tokens, first, *pos)).with_note("an array lives in the stack frame of the function that declares it"));
+                }
+                typ
+            } else {
+                ValueType::Int
+            };
 
-            let statement = statement(tokens, pos, &mut variables)?;
+            let statement = statement(tokens, pos, &mut scope, &mut Vec::new(), errors)?;
 
-            Ok(Node::Function { name: function_name.clone(), args_num, variables, statement: Box::new(statement) })
+            Ok(Node::Function { name: function_name.clone(), arg_types, return_type, variables: scope.variables, statement: Box::new(statement), span: span(tokens, first, *pos) })
         } else {
-            Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos))
+            Err(expected(tokens, *pos, "an identifier"))
         }
     } else {
-        Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos))
+        Err(expected(tokens, *pos, "`fn`, `let` or `const`"))
     }
 }
 
-fn statement(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
+fn value_type(tokens: &[Token], pos: &mut usize) -> Result<ValueType, CompileError> {
+    let typ = match tokens[*pos].typ {
+        TokenType::Word(Word::Int) => ValueType::Int,
+        TokenType::Word(Word::Float) => ValueType::Float,
+        TokenType::Word(Word::String) => ValueType::Str,
+        _ => return Err(expected(tokens, *pos, "a type")),
+    };
+    *pos += 1;
+
+    if typ != ValueType::Str && tokens[*pos].typ == TokenType::Symbol(Symbol::OpenSquare) {
+        *pos += 1;
+
+        return if tokens[*pos].typ == TokenType::Symbol(Symbol::CloseSquare) {
+            *pos += 1;
+            Ok(typ.array())
+        } else {
+            Err(expected(tokens, *pos, "`]`"))
+        };
+    }
+
+    Ok(typ)
+}
+
+fn statement(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope, labels: &mut Vec<Option<String>>, errors: &mut Vec<CompileError>) -> Result<Node, CompileError> {
+    let first = *pos;
+
     if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenBrace) {
         *pos += 1;
 
         let mut statements = Vec::new();
 
+        scope.enter();
         while tokens[*pos].typ != TokenType::Symbol(Symbol::CloseBrace) {
-            let statement = statement(tokens, pos, variables)?;
-            statements.push(statement);
+            // An unclosed block ends at the next function; blocks left open by the same brace report it once.
+            if tokens[*pos].typ == TokenType::Eof || tokens[*pos].typ == TokenType::Word(Word::Function) {
+                let error = expected(tokens, *pos, "`}`");
+                if errors.last() != Some(&error) {
+                    errors.push(error);
+                }
+                break;
+            }
+
+            let statement_first = *pos;
+            match statement(tokens, pos, scope, labels, errors) {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    errors.push(error);
+                    synchronize(tokens, pos, statement_first);
+                },
+            }
+        }
+        scope.leave();
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::CloseBrace) {
+            *pos += 1;
         }
-        *pos += 1;
 
-        Ok(Node::Block { statements })
+        Ok(Node::Block { statements, span: span(tokens, first, *pos) })
     } else if tokens[*pos].typ == TokenType::Word(Word::If) {
         *pos += 1;
 
-        let condition = expression(tokens, pos, variables)?;
+        let condition = expression(tokens, pos, scope)?;
 
-        let true_case = statement(tokens, pos, variables)?;
+        let true_case = statement(tokens, pos, scope, labels, errors)?;
 
         let false_case = if tokens[*pos].typ == TokenType::Word(Word::Else) {
             *pos += 1;
 
-            Some(statement(tokens, pos, variables)?)
+            Some(statement(tokens, pos, scope, labels, errors)?)
         } else {
             None
         };
 
-        Ok(Node::If { condition: Box::new(condition), true_case: Box::new(true_case), false_case: Box::new(false_case) })
-    } else if tokens[*pos].typ == TokenType::Word(Word::For) {
+        Ok(Node::If { condition: Box::new(condition), true_case: Box::new(true_case), false_case: Box::new(false_case), span: span(tokens, first, *pos) })
+    } else if let TokenType::Label(label) = &tokens[*pos].typ {
         *pos += 1;
 
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::Colon) {
+            *pos += 1;
+        } else {
+            return Err(expected(tokens, *pos, "`:`"));
+        }
+
+        if labels.contains(&Some(label.clone())) {
+            return Err(CompileError::new(ErrorKind::DuplicateLabel, format!("duplicate label '{}", label), tokens[*pos - 2].span()));
+        }
+
+        loop_statement(tokens, pos, scope, labels, errors, Some(label.clone()), first)
+    } else if tokens[*pos].typ == TokenType::Word(Word::For) || tokens[*pos].typ == TokenType::Word(Word::While) || tokens[*pos].typ == TokenType::Word(Word::Do) || tokens[*pos].typ == TokenType::Word(Word::Loop) {
+        loop_statement(tokens, pos, scope, labels, errors, None, first)
+    } else if tokens[*pos].typ == TokenType::Word(Word::Match) {
+        match_statement(tokens, pos, scope, labels, errors)
+    } else if tokens[*pos].typ == TokenType::Word(Word::Let) {
+        let (offset, typ, value) = let_statement(tokens, pos, scope)?;
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
+            *pos += 1;
+
+            Ok(Node::Let { offset, typ, value: Box::new(value), span: span(tokens, first, *pos) })
+        } else {
+            Err(expected(tokens, *pos, "`;`"))
+        }
+    } else if tokens[*pos].typ == TokenType::Word(Word::Break) {
+        *pos += 1;
+
+        let label = loop_label(tokens, pos, labels)?;
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
+            *pos += 1;
+
+            Ok(Node::Break { label, span: span(tokens, first, *pos) })
+        } else {
+            Err(expected(tokens, *pos, "`;`"))
+        }
+    } else if tokens[*pos].typ == TokenType::Word(Word::Continue) {
+        *pos += 1;
+
+        let label = loop_label(tokens, pos, labels)?;
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
+            *pos += 1;
+
+            Ok(Node::Continue { label, span: span(tokens, first, *pos) })
+        } else {
+            Err(expected(tokens, *pos, "`;`"))
+        }
+    } else {
+        let expression = expression(tokens, pos, scope)?;
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
+            *pos += 1;
+
+            Ok(Node::Statement { node: Box::new(expression), span: span(tokens, first, *pos) })
+        } else if tokens[*pos].typ == TokenType::Symbol(Symbol::Return) {
+            *pos += 1;
+
+            Ok(Node::Return { node: Box::new(expression), span: span(tokens, first, *pos) })
+        } else {
+            Err(expected(tokens, *pos, "`;` or `@`"))
+        }
+    }
+}
+
+// `let name[: type] = value`, without the closing `;`, which the caller includes in the span; the name only becomes visible after its value.
+fn let_statement(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<(usize, Option<ValueType>, Node), CompileError> {
+    *pos += 1;
+    let name_pos = *pos;
+
+    let (name, typ, value) = match declaration(tokens, pos, scope) {
+        Ok(declaration) => declaration,
+        Err(error) => {
+            // The name is declared anyway, so that its uses further on are not reported too.
+            if let TokenType::Ident(name) = &tokens[name_pos].typ {
+                scope.declare(name);
+            }
+            return Err(error);
+        },
+    };
+    let offset = scope.declare(&name);
+
+    Ok((offset, typ, value))
+}
+
+// The `name[: type] = value` part shared by `let` and `const`.
+fn declaration(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<(String, Option<ValueType>, Node), CompileError> {
+    let name = if let TokenType::Ident(name) = &tokens[*pos].typ {
+        *pos += 1;
+        name.clone()
+    } else {
+        return Err(expected(tokens, *pos, "an identifier"));
+    };
+
+    let typ = if tokens[*pos].typ == TokenType::Symbol(Symbol::Colon) {
+        *pos += 1;
+
+        Some(value_type(tokens, pos)?)
+    } else {
+        None
+    };
+
+    if tokens[*pos].typ == TokenType::Symbol(Symbol::Assign) {
+        *pos += 1;
+    } else {
+        return Err(expected(tokens, *pos, "`=`"));
+    }
+
+    let value = expression(tokens, pos, scope)?;
+
+    Ok((name, typ, value))
+}
+
+// `match value { 0 => ..., 1 | 2 => ..., 3..=9 => ..., _ => ... }`, where an arm is a block, or an expression, `break` or `continue` followed by `,`.
+// Each pattern is an inclusive range of ints, and no value may be matched by two arms.
+fn match_statement(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope, labels: &mut Vec<Option<String>>, errors: &mut Vec<CompileError>) -> Result<Node, CompileError> {
+    let first = *pos;
+    *pos += 1;
+
+    let value = expression(tokens, pos, scope)?;
+
+    if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenBrace) {
+        *pos += 1;
+    } else {
+        return Err(expected(tokens, *pos, "`{`"));
+    }
+
+    let mut arms = Vec::new();
+    let mut default = None;
+    let mut default_span = Span::default();
+    let mut ranges: Vec<(i64, i64, Span)> = Vec::new();
+
+    while tokens[*pos].typ != TokenType::Symbol(Symbol::CloseBrace) {
+        if default.is_some() {
+            return Err(CompileError::new(ErrorKind::UnreachableArm, "unreachable match arm", tokens[*pos].span()).with_label(default_span, "`_` already matches every value"));
+        }
+
+        let mut patterns = Vec::new();
+        let mut wildcard = false;
+        loop {
+            let pattern_first = *pos;
+
+            if tokens[*pos].typ == TokenType::Ident("_".to_string()) {
+                default_span = tokens[*pos].span();
+                *pos += 1;
+                wildcard = true;
+            } else {
+                let lo = pattern_value(tokens, pos, scope)?;
+                let hi = match tokens[*pos].typ {
+                    TokenType::Symbol(Symbol::RangeInclusive) => { *pos += 1; Some(pattern_value(tokens, pos, scope)?) },
+                    TokenType::Symbol(Symbol::Range) => { *pos += 1; pattern_value(tokens, pos, scope)?.checked_sub(1) },
+                    _ => Some(lo),
+                };
+                let hi = match hi {
+                    Some(hi) if hi >= lo => hi,
+                    _ => return Err(CompileError::new(ErrorKind::EmptyRange, "empty range", span(tokens, pattern_first, *pos))),
+                };
+
+                if let Some((_, _, other)) = ranges.iter().find(|(other_lo, other_hi, _)| lo <= *other_hi && *other_lo <= hi) {
+                    return Err(CompileError::new(ErrorKind::OverlappingArm, "overlapping match arm", span(tokens, pattern_first, *pos)).with_label(other.clone(), "overlaps with this pattern"));
+                }
+                ranges.push((lo, hi, span(tokens, pattern_first, *pos)));
+                patterns.push((lo, hi));
+            }
+
+            if tokens[*pos].typ == TokenType::Symbol(Symbol::BitOr) {
+                *pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::FatArrow) {
+            *pos += 1;
+        } else {
+            return Err(expected(tokens, *pos, "`=>`"));
+        }
+
+        let body = if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenBrace) {
+            let body = statement(tokens, pos, scope, labels, errors)?;
+            if tokens[*pos].typ == TokenType::Symbol(Symbol::Comma) {
+                *pos += 1;
+            }
+            body
+        } else {
+            let body_first = *pos;
+            let body = if tokens[*pos].typ == TokenType::Word(Word::Break) {
+                *pos += 1;
+                Node::Break { label: loop_label(tokens, pos, labels)?, span: span(tokens, body_first, *pos) }
+            } else if tokens[*pos].typ == TokenType::Word(Word::Continue) {
+                *pos += 1;
+                Node::Continue { label: loop_label(tokens, pos, labels)?, span: span(tokens, body_first, *pos) }
+            } else {
+                let node = expression(tokens, pos, scope)?;
+                Node::Statement { node: Box::new(node), span: span(tokens, body_first, *pos) }
+            };
+            if tokens[*pos].typ == TokenType::Symbol(Symbol::Comma) {
+                *pos += 1;
+            } else if tokens[*pos].typ != TokenType::Symbol(Symbol::CloseBrace) {
+                return Err(expected(tokens, *pos, "`,` or `}`"));
+            }
+            body
+        };
+
+        if wildcard {
+            default = Some(body);
+        } else {
+            arms.push((patterns, body));
+        }
+    }
+    *pos += 1;
+
+    Ok(Node::Match { value: Box::new(value), arms, default: Box::new(default), span: span(tokens, first, *pos) })
+}
+
+// An int literal, optionally negated, or an int const.
+fn pattern_value(tokens: &[Token], pos: &mut usize, scope: &Scope) -> Result<i64, CompileError> {
+    let negative = tokens[*pos].typ == TokenType::Symbol(Symbol::Sub);
+    if negative {
+        *pos += 1;
+    }
+
+    let value = match &tokens[*pos].typ {
+        TokenType::Number(num) => *num,
+        TokenType::Ident(name) => if let Some(Node::Number { num, span: _ }) = scope.global(name) { *num } else { return Err(expected(tokens, *pos, "an int literal or const")) },
+        _ => return Err(expected(tokens, *pos, "an int literal or const")),
+    };
+    *pos += 1;
+
+    Ok(if negative { value.wrapping_neg() } else { value })
+}
+
+// `first` is the loop's label when it has one, or else its keyword.
+fn loop_statement(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope, labels: &mut Vec<Option<String>>, errors: &mut Vec<CompileError>, label: Option<String>, first: usize) -> Result<Node, CompileError> {
+    labels.push(label.clone());
+    scope.enter();
+    let node = loop_body(tokens, pos, scope, labels, errors, label, first);
+    scope.leave();
+    labels.pop();
+
+    node
+}
+
+fn loop_body(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope, labels: &mut Vec<Option<String>>, errors: &mut Vec<CompileError>, label: Option<String>, first: usize) -> Result<Node, CompileError> {
+    if tokens[*pos].typ == TokenType::Word(Word::For) {
+        *pos += 1;
+
+        if matches!(tokens[*pos].typ, TokenType::Ident(_)) && matches!(&tokens[*pos + 1].typ, TokenType::Ident(word) if word == "in") {
+            return range_for(tokens, pos, scope, labels, errors, label, first);
+        }
+
         let init = if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
             None
+        } else if tokens[*pos].typ == TokenType::Word(Word::Let) {
+            let init_first = *pos;
+            let (offset, typ, value) = let_statement(tokens, pos, scope)?;
+            Some(Node::Let { offset, typ, value: Box::new(value), span: span(tokens, init_first, *pos) })
         } else {
-            Some(expression(tokens, pos, variables)?)
+            let init_first = *pos;
+            let node = expression(tokens, pos, scope)?;
+            Some(Node::Statement { node: Box::new(node), span: span(tokens, init_first, *pos) })
         };
         if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
             *pos += 1;
         } else {
-            return Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos));
+            return Err(expected(tokens, *pos, "`;`"));
         }
 
         let condition = if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
             None
         } else {
-            Some(expression(tokens, pos, variables)?)
+            Some(expression(tokens, pos, scope)?)
         };
         if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
             *pos += 1;
         } else {
-            return Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos));
+            return Err(expected(tokens, *pos, "`;`"));
         }
 
         let update = if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
             None
         } else {
-            Some(expression(tokens, pos, variables)?)
+            Some(expression(tokens, pos, scope)?)
         };
         if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
             *pos += 1;
         } else {
-            return Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos));
+            return Err(expected(tokens, *pos, "`;`"));
         }
 
-        let statement = statement(tokens, pos, variables)?;
+        let statement = statement(tokens, pos, scope, labels, errors)?;
 
-        Ok(Node::For { init: Box::new(init), condition: Box::new(condition), update: Box::new(update), statement: Box::new(statement) })
+        Ok(Node::For { label, init: Box::new(init), condition: Box::new(condition), update: Box::new(update), statement: Box::new(statement), span: span(tokens, first, *pos) })
     } else if tokens[*pos].typ == TokenType::Word(Word::While) {
         *pos += 1;
 
-        let condition = expression(tokens, pos, variables)?;
+        let condition = expression(tokens, pos, scope)?;
 
-        let statement = statement(tokens, pos, variables)?;
+        let statement = statement(tokens, pos, scope, labels, errors)?;
 
-        Ok(Node::While { condition: Box::new(condition), node: Box::new(statement) })
-    } else {
-        let expression = expression(tokens, pos, variables)?;
+        Ok(Node::While { label, condition: Box::new(condition), node: Box::new(statement), span: span(tokens, first, *pos) })
+    } else if tokens[*pos].typ == TokenType::Word(Word::Do) {
+        *pos += 1;
 
-        if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
-            *pos += 1;
+        let statement = statement(tokens, pos, scope, labels, errors)?;
 
-            Ok(Node::Statement { node: Box::new(expression) })
-        } else if tokens[*pos].typ == TokenType::Symbol(Symbol::Return) {
+        if tokens[*pos].typ == TokenType::Word(Word::While) {
             *pos += 1;
+        } else {
+            return Err(expected(tokens, *pos, "`while`"));
+        }
+
+        let condition = expression(tokens, pos, scope)?;
 
-            Ok(Node::Return { node: Box::new(expression) })
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::End) {
+            *pos += 1;
         } else {
-            Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos))
+            return Err(expected(tokens, *pos, "`;`"));
         }
-    }
-}
 
-fn expression(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    exchange(tokens, pos, variables)
-}
+        Ok(Node::DoWhile { label, node: Box::new(statement), condition: Box::new(condition), span: span(tokens, first, *pos) })
+    } else if tokens[*pos].typ == TokenType::Word(Word::Loop) {
+        *pos += 1;
 
-fn exchange(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let node = assign(tokens, pos, variables)?;
+        let statement = statement(tokens, pos, scope, labels, errors)?;
 
-    Ok(match tokens[*pos].typ {
-        TokenType::Symbol(Symbol::Exchange) => { *pos += 1; Node::Operator { typ: Operator::Exchange, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) } }
-        _ => node,
-    })
+        Ok(Node::Loop { label, node: Box::new(statement), span: span(tokens, first, *pos) })
+    } else {
+        Err(expected(tokens, *pos, "`for`, `while`, `do` or `loop`"))
+    }
 }
 
-fn assign(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let node = or(tokens, pos, variables)?;
+// `for i in [rev] a..b [step s] statement`, or `a..=b` to include `b`, with `rev` walking the same values from the top down:
+// `rev 0..10 step 4` gives 8, 4, 0, like Rust's `(0..10).step_by(4).rev()`.
+// The bounds are evaluated once into hidden slots, and `i` is a copy of the counter scoped to the body.
+// The step has to be a positive constant, since a step of 0 would never end the loop.
+// `in`, `rev` and `step` are only words here, so they stay free as names everywhere else; a `rev` right before `..` or `..=` is one too.
+fn range_for(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope, labels: &mut Vec<Option<String>>, errors: &mut Vec<CompileError>, label: Option<String>, first: usize) -> Result<Node, CompileError> {
+    let name = if let TokenType::Ident(name) = &tokens[*pos].typ { name.clone() } else { unreachable!() };
+    let name_span = tokens[*pos].span();
+    *pos += 2;
+
+    let reverse = matches!(&tokens[*pos].typ, TokenType::Ident(word) if word == "rev") && !matches!(tokens[*pos + 1].typ, TokenType::Symbol(Symbol::Range) | TokenType::Symbol(Symbol::RangeInclusive));
+    if reverse {
+        *pos += 1;
+    }
 
-    Ok(match tokens[*pos].typ {
-        TokenType::Symbol(Symbol::Assign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) } },
-        TokenType::Symbol(Symbol::AddAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Add, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::SubAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Sub, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::MulAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Mul, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::DivAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Div, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::RemAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Rem, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::PowerAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Power, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::RootAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Root, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::AndAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::And, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::XorAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Xor, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::OrAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::Or, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::LShiftAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::LShift, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::RShiftAssign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node.clone()), rhs: Box::new(Node::Operator { typ: Operator::RShift, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) }) } },
-        TokenType::Symbol(Symbol::ChangeMin) => { *pos += 1; Node::Operator { typ: Operator::ChangeMin, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) } },
-        TokenType::Symbol(Symbol::ChangeMax) => { *pos += 1; Node::Operator { typ: Operator::ChangeMax, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, variables)?) } },
-        _ => node,
-    })
-}
+    let from = expression(tokens, pos, scope)?;
 
-fn or(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = and(tokens, pos, variables)?;
+    let inclusive = match tokens[*pos].typ {
+        TokenType::Symbol(Symbol::Range) => false,
+        TokenType::Symbol(Symbol::RangeInclusive) => true,
+        _ => return Err(expected(tokens, *pos, "`..` or `..=`")),
+    };
+    *pos += 1;
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::Or) => { *pos += 1; Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0}), rhs: Box::new(Node::Operator { typ: Operator::Or, lhs: Box::new(node), rhs: Box::new(and(tokens, pos, variables)?) }) }) } },
-            _ => return Ok(node),
-        }
-    }
-}
+    let to = expression(tokens, pos, scope)?;
 
-fn and(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = bit_or(tokens, pos, variables)?;
+    let step = if matches!(&tokens[*pos].typ, TokenType::Ident(word) if word == "step") {
+        *pos += 1;
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::And) => { *pos += 1; Node::Operator { typ: Operator::And, lhs: Box::new(Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(node) }) }), rhs: Box::new(Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(bit_or(tokens, pos, variables)?) }) }) } },
-            _ => return Ok(node),
+        match constant::evaluate(&expression(tokens, pos, scope)?)? {
+            Node::Number { num, span } if num > 0 => Some(Node::Number { num, span }),
+            Node::Number { num: _, span } => return Err(CompileError::new(ErrorKind::NonPositiveStep, "step must be positive", span)),
+            Node::Float { num: _, span } => return Err(CompileError::new(ErrorKind::TypeMismatch, "type mismatch: expected int, found float", span)),
+            step => return Err(CompileError::new(ErrorKind::TypeMismatch, "type mismatch: expected int, found str", step.span().clone())),
         }
+    } else {
+        None
+    };
+
+    // The nodes that stand for no single part of the source get the whole header as their span.
+    let header = span(tokens, first, *pos);
+    let mut statements = Vec::new();
+
+    let counter = scope.declare("for.counter");
+    let bound = scope.declare("for.bound");
+    if reverse {
+        let to = if inclusive { to } else { let span = to.span().clone(); Node::Operator { typ: Operator::Sub, lhs: Box::new(to), rhs: Box::new(Node::Number { num: 1, span: span.clone() }), span } };
+        statements.push(Node::Let { offset: bound, typ: None, span: from.span().clone(), value: Box::new(from) });
+        statements.push(Node::Let { offset: counter, typ: None, span: to.span().clone(), value: Box::new(to) });
+    } else {
+        statements.push(Node::Let { offset: counter, typ: None, span: from.span().clone(), value: Box::new(from) });
+        statements.push(Node::Let { offset: bound, typ: None, span: to.span().clone(), value: Box::new(to) });
     }
-}
-
-fn bit_or(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = bit_xor(tokens, pos, variables)?;
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::BitOr) => { *pos += 1; Node::Operator { typ: Operator::Or, lhs: Box::new(node), rhs: Box::new(bit_xor(tokens, pos, variables)?) } },
-            _ => return Ok(node),
+    let variable = |offset| Box::new(Node::Variable { offset, span: header.clone() });
+
+    let step = if let Some(step) = step {
+        // Walking down starts from the last value the walk up would reach, `last - (last - a) % s`, unless the range is empty.
+        if reverse {
+            let distance = Node::Operator { typ: Operator::Sub, lhs: variable(counter), rhs: variable(bound), span: header.clone() };
+            let offset_from_last = Node::Operator { typ: Operator::Rem, lhs: Box::new(distance), rhs: Box::new(step.clone()), span: header.clone() };
+            let top = Node::Operator { typ: Operator::Sub, lhs: variable(counter), rhs: Box::new(offset_from_last), span: header.clone() };
+            let empty = Node::Operator { typ: Operator::Less, lhs: variable(counter), rhs: variable(bound), span: header.clone() };
+            let top = Node::Conditional { condition: Box::new(empty), true_case: variable(counter), false_case: Box::new(top), span: header.clone() };
+            statements.push(Node::Statement { node: Box::new(Node::Operator { typ: Operator::Assign, lhs: variable(counter), rhs: Box::new(top), span: header.clone() }), span: header.clone() });
         }
-    }
-}
 
-fn bit_xor(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = bit_and(tokens, pos, variables)?;
+        step
+    } else {
+        Node::Number { num: 1, span: header.clone() }
+    };
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::BitXor) => { *pos += 1; Node::Operator { typ: Operator::Xor, lhs: Box::new(node), rhs: Box::new(bit_and(tokens, pos, variables)?) } },
-            _ => return Ok(node),
+    let condition = match (reverse, inclusive) {
+        (true, _) => Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0, span: header.clone() }), rhs: Box::new(Node::Operator { typ: Operator::Less, lhs: variable(counter), rhs: variable(bound), span: header.clone() }), span: header.clone() },
+        (false, true) => Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0, span: header.clone() }), rhs: Box::new(Node::Operator { typ: Operator::Less, lhs: variable(bound), rhs: variable(counter), span: header.clone() }), span: header.clone() },
+        (false, false) => Node::Operator { typ: Operator::Less, lhs: variable(counter), rhs: variable(bound), span: header.clone() },
+    };
+    let update = Node::CompoundAssign { op: if reverse { Operator::Sub } else { Operator::Add }, target: variable(counter), value: Box::new(step), span: header.clone() };
+
+    let offset = scope.declare(&name);
+    let body = statement(tokens, pos, scope, labels, errors)?;
+    let body = Node::Block { statements: vec![Node::Let { offset, typ: None, value: variable(counter), span: name_span }, body], span: span(tokens, first, *pos) };
+
+    statements.push(Node::For { label, init: Box::new(None), condition: Box::new(Some(condition)), update: Box::new(Some(update)), statement: Box::new(body), span: span(tokens, first, *pos) });
+
+    Ok(Node::Block { statements, span: span(tokens, first, *pos) })
+}
+
+fn loop_label(tokens: &[Token], pos: &mut usize, labels: &[Option<String>]) -> Result<Option<String>, CompileError> {
+    if let TokenType::Label(label) = &tokens[*pos].typ {
+        if !labels.contains(&Some(label.clone())) {
+            return Err(CompileError::new(ErrorKind::UndefinedLabel, format!("undefined label '{}", label), tokens[*pos].span()));
         }
+        *pos += 1;
+
+        Ok(Some(label.clone()))
+    } else if labels.is_empty() {
+        Err(CompileError::new(ErrorKind::OutsideOfLoop, "outside of a loop", tokens[*pos - 1].span()))
+    } else {
+        Ok(None)
     }
 }
 
-fn bit_and(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = equality(tokens, pos, variables)?;
+fn expression(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    exchange(tokens, pos, scope)
+}
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::BitAnd) => { *pos += 1; Node::Operator { typ: Operator::And, lhs: Box::new(node), rhs: Box::new(equality(tokens, pos, variables)?) } },
-            _ => return Ok(node),
-        }
-    }
+fn exchange(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    let first = *pos;
+    let node = assign(tokens, pos, scope)?;
+
+    Ok(match tokens[*pos].typ {
+        TokenType::Symbol(Symbol::Exchange) => {
+            *pos += 1;
+            let rhs = assign(tokens, pos, scope)?;
+            if !node.is_assignable() {
+                return Err(not_assignable(node.span().clone()));
+            }
+            if !rhs.is_assignable() {
+                return Err(not_assignable(rhs.span().clone()));
+            }
+            Node::Operator { typ: Operator::Exchange, lhs: Box::new(node), rhs: Box::new(rhs), span: span(tokens, first, *pos) }
+        },
+        _ => node,
+    })
 }
 
-fn equality(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = relational(tokens, pos, variables)?;
+fn assign(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    let first = *pos;
+    let node = conditional(tokens, pos, scope)?;
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::Equal) => { *pos += 1; Node::Operator { typ: Operator::Equal, lhs: Box::new(node), rhs: Box::new(relational(tokens, pos, variables)?) } },
-            TokenType::Symbol(Symbol::NotEqual) => { *pos += 1; Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(Node::Operator { typ: Operator::Equal, lhs: Box::new(node), rhs: Box::new(relational(tokens, pos, variables)?) }) } },
-            _ => return Ok(node),
-        };
+    let is_assign = matches!(tokens[*pos].typ, TokenType::Symbol(Symbol::Assign | Symbol::AddAssign | Symbol::SubAssign | Symbol::MulAssign | Symbol::DivAssign | Symbol::RemAssign | Symbol::PowerAssign | Symbol::RootAssign | Symbol::AndAssign | Symbol::XorAssign | Symbol::OrAssign | Symbol::LShiftAssign | Symbol::RShiftAssign | Symbol::ChangeMin | Symbol::ChangeMax));
+    if is_assign && !node.is_assignable() {
+        return Err(not_assignable(node.span().clone()));
     }
+
+    Ok(match tokens[*pos].typ {
+        TokenType::Symbol(Symbol::Assign) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::AddAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Add, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::SubAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Sub, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::MulAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Mul, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::DivAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Div, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::RemAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Rem, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::PowerAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Power, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::RootAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Root, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::AndAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::And, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::XorAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Xor, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::OrAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::Or, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::LShiftAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::LShift, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::RShiftAssign) => { *pos += 1; Node::CompoundAssign { op: Operator::RShift, target: Box::new(node), value: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::ChangeMin) => { *pos += 1; Node::Operator { typ: Operator::ChangeMin, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::ChangeMax) => { *pos += 1; Node::Operator { typ: Operator::ChangeMax, lhs: Box::new(node), rhs: Box::new(assign(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        _ => node,
+    })
 }
 
-fn relational(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = shift(tokens, pos, variables)?;
+// `condition ? a : b`, looser than every binary operator and right associative.
+fn conditional(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    let first = *pos;
+    let node = binary(tokens, pos, scope, 0)?;
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::Less) => { *pos += 1; Node::Operator { typ: Operator::Less, lhs: Box::new(node), rhs: Box::new(shift(tokens, pos, variables)?) } },
-            TokenType::Symbol(Symbol::LessOrEqual) => { *pos += 1; Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(Node::Operator { typ: Operator::Less, lhs: Box::new(shift(tokens, pos, variables)?), rhs: Box::new(node) }) } },
-            TokenType::Symbol(Symbol::Greater) => { *pos += 1; Node::Operator { typ: Operator::Less, lhs: Box::new(shift(tokens, pos, variables)?), rhs: Box::new(node) } },
-            TokenType::Symbol(Symbol::GreaterOrEqual) => { *pos += 1; Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(Node::Operator { typ: Operator::Less, lhs: Box::new(node), rhs: Box::new(shift(tokens, pos, variables)?) }) } },
-            _ => return Ok(node),
-        };
+    if tokens[*pos].typ != TokenType::Symbol(Symbol::Question) {
+        return Ok(node);
     }
-}
+    *pos += 1;
 
-fn shift(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = add(tokens, pos, variables)?;
+    let true_case = expression(tokens, pos, scope)?;
 
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::LShift) => { *pos += 1; Node::Operator { typ: Operator::LShift, lhs: Box::new(node), rhs: Box::new(add(tokens, pos, variables)?) } },
-            TokenType::Symbol(Symbol::RShift) => { *pos += 1; Node::Operator { typ: Operator::RShift, lhs: Box::new(node), rhs: Box::new(add(tokens, pos, variables)?) } },
-            _ => return Ok(node),
-        };
+    if tokens[*pos].typ == TokenType::Symbol(Symbol::Colon) {
+        *pos += 1;
+    } else {
+        return Err(expected(tokens, *pos, "`:`"));
     }
+
+    let false_case = conditional(tokens, pos, scope)?;
+
+    Ok(Node::Conditional { condition: Box::new(node), true_case: Box::new(true_case), false_case: Box::new(false_case), span: span(tokens, first, *pos) })
 }
 
-fn add(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = mul(tokens, pos, variables)?;
+#[derive(Copy, Clone, PartialEq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+// Every binary operator with its precedence, loosest first.
+const BINARY_OPERATORS: [(Symbol, usize, Associativity); 20] = [
+    (Symbol::Or, 1, Associativity::Left),
+    (Symbol::And, 2, Associativity::Left),
+    (Symbol::BitOr, 3, Associativity::Left),
+    (Symbol::BitXor, 4, Associativity::Left),
+    (Symbol::BitAnd, 5, Associativity::Left),
+    (Symbol::Equal, 6, Associativity::Left),
+    (Symbol::NotEqual, 6, Associativity::Left),
+    (Symbol::Less, 7, Associativity::Left),
+    (Symbol::LessOrEqual, 7, Associativity::Left),
+    (Symbol::Greater, 7, Associativity::Left),
+    (Symbol::GreaterOrEqual, 7, Associativity::Left),
+    (Symbol::LShift, 8, Associativity::Left),
+    (Symbol::RShift, 8, Associativity::Left),
+    (Symbol::Add, 9, Associativity::Left),
+    (Symbol::Sub, 9, Associativity::Left),
+    (Symbol::Mul, 10, Associativity::Left),
+    (Symbol::Div, 10, Associativity::Left),
+    (Symbol::Rem, 10, Associativity::Left),
+    (Symbol::Power, 11, Associativity::Right),
+    (Symbol::Root, 11, Associativity::Right),
+];
+
+// Precedence climbing: only operators binding at least as tightly as `min_precedence` are taken.
+fn binary(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope, min_precedence: usize) -> Result<Node, CompileError> {
+    let first = *pos;
+    let mut node = unary(tokens, pos, scope)?;
 
     loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::Add) => { *pos += 1; Node::Operator { typ: Operator::Add, lhs: Box::new(node), rhs: Box::new(add(tokens, pos, variables)?) } },
-            TokenType::Symbol(Symbol::Sub) => { *pos += 1; Node::Operator { typ: Operator::Sub, lhs: Box::new(node), rhs: Box::new(add(tokens, pos, variables)?) } },
+        let operator = BINARY_OPERATORS.iter().find(|(symbol, _, _)| tokens[*pos].typ == TokenType::Symbol(*symbol));
+        let (symbol, precedence, associativity) = match operator {
+            Some(&(symbol, precedence, associativity)) if precedence >= min_precedence => (symbol, precedence, associativity),
             _ => return Ok(node),
         };
+        *pos += 1;
+
+        let rhs = binary(tokens, pos, scope, if associativity == Associativity::Left { precedence + 1 } else { precedence })?;
+        node = binary_node(symbol, node, rhs, span(tokens, first, *pos));
     }
 }
 
-fn mul(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let mut node = power_root(tokens, pos, variables)?;
-
-    loop {
-        node = match tokens[*pos].typ {
-            TokenType::Symbol(Symbol::Mul) => { *pos += 1; Node::Operator { typ: Operator::Mul, lhs: Box::new(node), rhs: Box::new(power_root(tokens, pos, variables)?) } },
-            TokenType::Symbol(Symbol::Div) => { *pos += 1; Node::Operator { typ: Operator::Div, lhs: Box::new(node), rhs: Box::new(power_root(tokens, pos, variables)?) } },
-            TokenType::Symbol(Symbol::Rem) => { *pos += 1; Node::Operator { typ: Operator::Rem, lhs: Box::new(node), rhs: Box::new(power_root(tokens, pos, variables)?) } },
-            _ => return Ok(node),
-        };
+fn binary_node(symbol: Symbol, lhs: Node, rhs: Node, span: Span) -> Node {
+    match symbol {
+        Symbol::Or => Node::Logical { typ: Logical::Or, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::And => Node::Logical { typ: Logical::And, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::BitOr => Node::Operator { typ: Operator::Or, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::BitXor => Node::Operator { typ: Operator::Xor, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::BitAnd => Node::Operator { typ: Operator::And, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Equal => Node::Operator { typ: Operator::Equal, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::NotEqual => Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0, span: span.clone() }), rhs: Box::new(Node::Operator { typ: Operator::Equal, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() }), span: span.clone() },
+        Symbol::Less => Node::Operator { typ: Operator::Less, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::LessOrEqual => Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0, span: span.clone() }), rhs: Box::new(Node::Operator { typ: Operator::Less, lhs: Box::new(rhs), rhs: Box::new(lhs), span: span.clone() }), span: span.clone() },
+        Symbol::Greater => Node::Operator { typ: Operator::Less, lhs: Box::new(rhs), rhs: Box::new(lhs), span: span.clone() },
+        Symbol::GreaterOrEqual => Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0, span: span.clone() }), rhs: Box::new(Node::Operator { typ: Operator::Less, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() }), span: span.clone() },
+        Symbol::LShift => Node::Operator { typ: Operator::LShift, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::RShift => Node::Operator { typ: Operator::RShift, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Add => Node::Operator { typ: Operator::Add, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Sub => Node::Operator { typ: Operator::Sub, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Mul => Node::Operator { typ: Operator::Mul, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Div => Node::Operator { typ: Operator::Div, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Rem => Node::Operator { typ: Operator::Rem, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Power => Node::Operator { typ: Operator::Power, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        Symbol::Root => Node::Operator { typ: Operator::Root, lhs: Box::new(lhs), rhs: Box::new(rhs), span: span.clone() },
+        _ => unreachable!(),
     }
 }
 
-fn power_root(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
-    let node = unary(tokens, pos, variables)?;
+fn unary(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    let first = *pos;
 
     Ok(match tokens[*pos].typ {
-        TokenType::Symbol(Symbol::Power) => { *pos += 1; Node::Operator { typ: Operator::Power, lhs: Box::new(node), rhs: Box::new(power_root(tokens, pos, variables)?) } },
-        TokenType::Symbol(Symbol::Root) => { *pos += 1; Node::Operator { typ: Operator::Root, lhs: Box::new(node), rhs: Box::new(power_root(tokens, pos, variables)?) } },
-        _ => return Ok(node),
+        TokenType::Symbol(Symbol::Sub) => { *pos += 1; Node::Operator { typ: Operator::Sub, lhs: Box::new(Node::Number { num: 0, span: tokens[first].span() }), rhs: Box::new(unary(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::BitNot) => { *pos += 1; Node::Operator { typ: Operator::Xor, lhs: Box::new(Node::Number { num: -1, span: tokens[first].span() }), rhs: Box::new(unary(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::Not) => { *pos += 1; Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0, span: tokens[first].span() }), rhs: Box::new(unary(tokens, pos, scope)?), span: span(tokens, first, *pos) } },
+        TokenType::Symbol(Symbol::Increment) => { *pos += 1; increment(Operator::Add, true, unary(tokens, pos, scope)?, span(tokens, first, *pos))? },
+        TokenType::Symbol(Symbol::Decrement) => { *pos += 1; increment(Operator::Sub, true, unary(tokens, pos, scope)?, span(tokens, first, *pos))? },
+        _ => postfix(tokens, pos, scope)?,
     })
 }
 
-fn unary(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
+fn postfix(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    let first = *pos;
+    let node = primary(tokens, pos, scope)?;
+
     Ok(match tokens[*pos].typ {
-        TokenType::Symbol(Symbol::Sub) => { *pos += 1; Node::Operator { typ: Operator::Sub, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(primary(tokens, pos, variables)?) } },
-        TokenType::Symbol(Symbol::BitNot) => { *pos += 1; Node::Operator { typ: Operator::Xor, lhs: Box::new(Node::Number { num: -1 }), rhs: Box::new(primary(tokens, pos, variables)?) } },
-        TokenType::Symbol(Symbol::Not) => { *pos += 1; Node::Operator { typ: Operator::Equal, lhs: Box::new(Node::Number { num: 0 }), rhs: Box::new(primary(tokens, pos, variables)?) } },
-        _ => primary(tokens, pos, variables)?,
+        TokenType::Symbol(Symbol::Increment) => { *pos += 1; increment(Operator::Add, false, node, span(tokens, first, *pos))? },
+        TokenType::Symbol(Symbol::Decrement) => { *pos += 1; increment(Operator::Sub, false, node, span(tokens, first, *pos))? },
+        _ => node,
     })
 }
 
-fn primary(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) -> Result<Node, String> {
+// `++x` gives the new value and `x++` the old one, like in C.
+fn increment(op: Operator, prefix: bool, target: Node, span: Span) -> Result<Node, CompileError> {
+    if target.is_assignable() {
+        Ok(Node::Increment { op, prefix, target: Box::new(target), span })
+    } else {
+        Err(not_assignable(span))
+    }
+}
+
+fn primary(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    let first = *pos;
+
     if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenBracket) {
         *pos += 1;
 
-        let node = expression(tokens, pos, variables)?;
+        let node = expression(tokens, pos, scope)?;
 
         if tokens[*pos].typ == TokenType::Symbol(Symbol::CloseBracket) {
             *pos += 1;
             Ok(node)
         } else {
-            Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos))
+            Err(expected(tokens, *pos, "`)`"))
         }
     } else if let TokenType::Ident(ident_name) = &tokens[*pos].typ {
         *pos += 1;
@@ -357,41 +882,359 @@ fn primary(tokens: &Vec<Token>, pos: &mut usize, variables: &mut Vec<String>) ->
 
             let mut arguments = Vec::new();
 
-            let mut first = true;
+            let mut first_argument = true;
             while tokens[*pos].typ != TokenType::Symbol(Symbol::CloseBracket) {
-                if !first {
+                if !first_argument {
                     if tokens[*pos].typ == TokenType::Symbol(Symbol::Comma) {
                         *pos += 1;
                     } else {
-                        return Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos));
+                        return Err(expected(tokens, *pos, "`,` or `)`"));
                     }
                 }
-                first = false;
+                first_argument = false;
 
-                let expr = expression(tokens, pos, variables)?;
+                let expr = expression(tokens, pos, scope)?;
                 arguments.push(expr);
             }
             *pos += 1;
 
-            Ok(Node::FuncCall { function_name: ident_name.clone(), arguments })
+            return Ok(Node::FuncCall { function_name: ident_name.clone(), arguments, span: span(tokens, first, *pos) });
+        }
+
+        let node = if let Some(offset) = scope.find(ident_name) {
+            Node::Variable { offset, span: tokens[first].span() }
+        } else if let Some(node) = scope.global(ident_name) {
+            // A const is inlined where it is named, so its literal takes the name's span.
+            match node {
+                Node::Number { num, span: _ } => Node::Number { num: *num, span: tokens[first].span() },
+                Node::Float { num, span: _ } => Node::Float { num: *num, span: tokens[first].span() },
+                Node::Str { text, span: _ } => Node::Str { text: text.clone(), span: tokens[first].span() },
+                Node::GlobalVariable { index, span: _ } => Node::GlobalVariable { index: *index, span: tokens[first].span() },
+                _ => node.clone(),
+            }
         } else {
-            for i in 0..variables.len() {
-                if variables[i] == *ident_name {
-                    return Ok(match tokens[*pos].typ {
-                        TokenType::Symbol(Symbol::Increment) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(Node::Variable { offset: i }), rhs: Box::new(Node::Operator { typ: Operator::Add, lhs: Box::new(Node::Variable { offset: i }), rhs: Box::new(Node::Number { num: 1 }) }) } },
-                        TokenType::Symbol(Symbol::Decrement) => { *pos += 1; Node::Operator { typ: Operator::Assign, lhs: Box::new(Node::Variable { offset: i }), rhs: Box::new(Node::Operator { typ: Operator::Sub, lhs: Box::new(Node::Variable { offset: i }), rhs: Box::new(Node::Number { num: 1 }) }) } },
-                        _ => Node::Variable { offset: i },
-                    });
-                }
+            return Err(CompileError::new(ErrorKind::UndeclaredVariable, format!("undeclared variable `{}`", ident_name), tokens[first].span()).with_help(format!("declare it first with `let {} = ...;`", ident_name)));
+        };
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenSquare) {
+            *pos += 1;
+
+            let index = expression(tokens, pos, scope)?;
+
+            if tokens[*pos].typ == TokenType::Symbol(Symbol::CloseSquare) {
+                *pos += 1;
+                Ok(Node::Index { array: Box::new(node), index: Box::new(index), span: span(tokens, first, *pos) })
+            } else {
+                Err(expected(tokens, *pos, "`]`"))
             }
-            let offset = variables.len();
-            variables.push(ident_name.clone());
-            Ok(Node::Variable { offset })
+        } else {
+            Ok(node)
+        }
+    } else if tokens[*pos].typ == TokenType::Word(Word::If) {
+        // `if condition { a } else { b }` as an expression; `else if` chains without braces.
+        *pos += 1;
+
+        let condition = expression(tokens, pos, scope)?;
+
+        let true_case = braced_expression(tokens, pos, scope)?;
+
+        if tokens[*pos].typ == TokenType::Word(Word::Else) {
+            *pos += 1;
+        } else {
+            return Err(expected(tokens, *pos, "`else`"));
+        }
+
+        let false_case = if tokens[*pos].typ == TokenType::Word(Word::If) {
+            primary(tokens, pos, scope)?
+        } else {
+            braced_expression(tokens, pos, scope)?
+        };
+
+        Ok(Node::Conditional { condition: Box::new(condition), true_case: Box::new(true_case), false_case: Box::new(false_case), span: span(tokens, first, *pos) })
+    } else if tokens[*pos].typ == TokenType::Word(Word::Int) || tokens[*pos].typ == TokenType::Word(Word::Float) {
+        let typ = if tokens[*pos].typ == TokenType::Word(Word::Int) { ValueType::Int } else { ValueType::Float };
+        *pos += 1;
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenSquare) {
+            *pos += 1;
+
+            // The length is a literal or an int const.
+            let len = match &tokens[*pos].typ {
+                TokenType::Number(len) => Some(*len),
+                TokenType::Ident(name) => if let Some(Node::Number { num, span: _ }) = scope.global(name) { Some(*num) } else { None },
+                _ => None,
+            };
+            let len = if let Some(len) = len {
+                if len <= 0 {
+                    return Err(CompileError::new(ErrorKind::ArrayLength, "array length must be positive", tokens[*pos].span()));
+                }
+                *pos += 1;
+                len as usize
+            } else {
+                return Err(expected(tokens, *pos, "an array length"));
+            };
+
+            return if tokens[*pos].typ == TokenType::Symbol(Symbol::CloseSquare) {
+                *pos += 1;
+                Ok(Node::Array { typ: typ.array(), len, span: span(tokens, first, *pos) })
+            } else {
+                Err(expected(tokens, *pos, "`]`"))
+            };
+        }
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenBracket) {
+            *pos += 1;
+        } else {
+            return Err(expected(tokens, *pos, "`(`"));
+        }
+
+        let node = expression(tokens, pos, scope)?;
+
+        if tokens[*pos].typ == TokenType::Symbol(Symbol::CloseBracket) {
+            *pos += 1;
+            Ok(Node::Cast { typ, node: Box::new(node), span: span(tokens, first, *pos) })
+        } else {
+            Err(expected(tokens, *pos, "`)`"))
         }
     } else if let TokenType::Number(num) = &tokens[*pos].typ {
         *pos += 1;
-        Ok(Node::Number { num: *num })
+        Ok(Node::Number { num: *num, span: tokens[first].span() })
+    } else if let TokenType::Float(num) = &tokens[*pos].typ {
+        *pos += 1;
+        Ok(Node::Float { num: *num, span: tokens[first].span() })
+    } else if let TokenType::Str(text) = &tokens[*pos].typ {
+        *pos += 1;
+        Ok(Node::Str { text: text.clone(), span: tokens[first].span() })
+    } else {
+        Err(expected(tokens, *pos, "an expression"))
+    }
+}
+
+fn braced_expression(tokens: &Vec<Token>, pos: &mut usize, scope: &mut Scope) -> Result<Node, CompileError> {
+    if tokens[*pos].typ == TokenType::Symbol(Symbol::OpenBrace) {
+        *pos += 1;
+    } else {
+        return Err(expected(tokens, *pos, "`{`"));
+    }
+
+    let node = expression(tokens, pos, scope)?;
+
+    if tokens[*pos].typ == TokenType::Symbol(Symbol::CloseBrace) {
+        *pos += 1;
+        Ok(node)
     } else {
-        Err(format!("Unexpected Token ({}:{})", tokens[*pos].line, tokens[*pos].pos))
+        Err(expected(tokens, *pos, "`}`"))
+    }
+}
+
+fn not_assignable(span: Span) -> CompileError {
+    CompileError::new(ErrorKind::NotAssignable, "not assignable", span).with_help("only variables, globals and array elements can be assigned to")
+}
+
+// `what` names everything the grammar allows at `pos`.
+fn expected(tokens: &[Token], pos: usize, what: &str) -> CompileError {
+    let found = &tokens[pos];
+    let message = format!("expected {}, found {}", what, found.typ.describe());
+
+    // A missing `;` belongs right after the previous token, which may well be on an earlier line.
+    if what == "`;`" && pos > 0 {
+        let previous = &tokens[pos - 1];
+        CompileError::new(ErrorKind::UnexpectedToken, message, Span::at(previous.line, previous.pos + previous.len)).with_label(found.span(), "unexpected token").with_help("add `;` here")
+    } else {
+        CompileError::new(ErrorKind::UnexpectedToken, message, found.span())
+    }
+}
+
+// Panic-mode recovery from an error at `pos` in the statement or item starting at `first`: skips past the `;` or the closing `}` that ends it,
+// or up to a `}` closing the enclosing block or the next `fn`. Braces are counted from `first`, so a block the error left open is skipped whole.
+fn synchronize(tokens: &[Token], pos: &mut usize, first: usize) {
+    let mut depth = 0;
+    let mut at = first;
+    loop {
+        match tokens[at].typ {
+            TokenType::Eof | TokenType::Word(Word::Function) => break,
+            TokenType::Symbol(Symbol::OpenBrace) => depth += 1,
+            TokenType::Symbol(Symbol::CloseBrace) if depth == 0 => break,
+            TokenType::Symbol(Symbol::CloseBrace) => {
+                depth -= 1;
+                if depth == 0 && at >= *pos {
+                    at += 1;
+                    break;
+                }
+            },
+            TokenType::Symbol(Symbol::End) if depth == 0 && at >= *pos => {
+                at += 1;
+                break;
+            },
+            _ => (),
+        }
+        at += 1;
     }
-}
\ No newline at end of file
+
+    *pos = at;
+}
+
+// From the token at `first` through the one just before `pos`.
+fn span(tokens: &[Token], first: usize, pos: usize) -> Span {
+    let last = &tokens[pos.max(first + 1) - 1];
+    Span::new(tokens[first].line, tokens[first].pos, last.line, last.pos + last.len)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compile_error::Span;
+    use crate::parser::node::Node;
+    use crate::parser::parse;
+    use crate::tokenizer::tokenize;
+
+    // Operators of each precedence level, loosest first.
+    const LEVELS: [&[&str]; 11] = [
+        &["||"],
+        &["&&"],
+        &["|"],
+        &["^"],
+        &["&"],
+        &["==", "!="],
+        &["<", "<==", ">", ">=="],
+        &["<<", ">>"],
+        &["+", "-"],
+        &["*", "/", "%"],
+        &["**", "//"],
+    ];
+
+    // Spans differ between the two spellings being compared, so they are left out.
+    fn tree(expression: &str) -> String {
+        let src = format!("fn main {{ let a = 1; let b = 2; let c = 3; {} @ }}", expression);
+        let (program, errors) = parse(tokenize(&src).unwrap());
+        assert_eq!(errors, Vec::new());
+        let mut tree = format!("{:?}", program);
+        while let Some(start) = tree.find(", span: Span {") {
+            let end = start + tree[start..].find('}').unwrap() + 1;
+            tree.replace_range(start..end, "");
+        }
+        tree
+    }
+
+    fn assert_grouping(expression: &str, grouped: &str) {
+        assert_eq!(tree(expression), tree(grouped), "`{}` should parse as `{}`", expression, grouped);
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        assert_grouping("10 - 3 - 2", "(10 - 3) - 2");
+    }
+
+    #[test]
+    fn left_associative_operators() {
+        for level in &LEVELS[..10] {
+            for lhs in level.iter() {
+                for rhs in level.iter() {
+                    assert_grouping(&format!("a {} b {} c", lhs, rhs), &format!("(a {} b) {} c", lhs, rhs));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn right_associative_operators() {
+        for lhs in LEVELS[10] {
+            for rhs in LEVELS[10] {
+                assert_grouping(&format!("a {} b {} c", lhs, rhs), &format!("a {} (b {} c)", lhs, rhs));
+            }
+        }
+    }
+
+    #[test]
+    fn tighter_operators_group_first() {
+        for (i, looser) in LEVELS.iter().enumerate() {
+            for tighter in &LEVELS[i + 1..] {
+                for loose in looser.iter() {
+                    for tight in tighter.iter() {
+                        assert_grouping(&format!("a {} b {} c", loose, tight), &format!("a {} (b {} c)", loose, tight));
+                        assert_grouping(&format!("a {} b {} c", tight, loose), &format!("(a {} b) {} c", tight, loose));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prefix_operators_bind_tighter_than_binary_ones() {
+        for prefix in ["-", "~", "!", "++", "--"] {
+            assert_grouping(&format!("{}a ** b", prefix), &format!("({}a) ** b", prefix));
+            assert_grouping(&format!("a * {}b", prefix), &format!("a * ({}b)", prefix));
+        }
+    }
+
+    #[test]
+    fn postfix_operators_bind_tighter_than_prefix_ones() {
+        for postfix in ["++", "--"] {
+            assert_grouping(&format!("-a{} ** b", postfix), &format!("(-(a{})) ** b", postfix));
+            assert_grouping(&format!("!a{}", postfix), &format!("!(a{})", postfix));
+        }
+    }
+
+    #[test]
+    fn conditional_is_right_associative_and_looser_than_binary_operators() {
+        assert_grouping("a ? b : c ? a : b", "a ? b : (c ? a : b)");
+        assert_grouping("a ? b ? c : a : b", "a ? (b ? c : a) : b");
+        assert_grouping("a || b ? a + b : c * a", "(a || b) ? (a + b) : (c * a)");
+        assert_grouping("a = b ? c : a", "a = (b ? c : a)");
+        assert_grouping("a < b ? a : b", "(if a < b { a } else { b })");
+    }
+
+    #[test]
+    fn assignments_are_right_associative_and_loosest() {
+        for assign in ["=", "+=", "-=", "*=", "/=", "%=", "**=", "//=", "&=", "^=", "|=", "<<=", ">>=", "<=", ">="] {
+            assert_grouping(&format!("a {} b {} c", assign, assign), &format!("a {} (b {} c)", assign, assign));
+            assert_grouping(&format!("a {} b || c", assign), &format!("a {} (b || c)", assign));
+        }
+    }
+
+    #[test]
+    fn range_words_are_still_names() {
+        let src = "fn main { let rev = 2; let step = 1; for in in rev..5 step 1 {} for i in rev rev..=5 {} for i in 0..5 step 2 { step += i; } 0 @ }";
+        let (_, errors) = parse(tokenize(src).unwrap());
+        assert_eq!(errors, Vec::new());
+    }
+
+    #[test]
+    fn spans_run_from_the_first_token_to_the_last() {
+        let (program, _) = parse(tokenize("fn main {\n    let a = 1;\n    a + (a * 2) @\n}").unwrap());
+        let Node::Program { globals: _, functions, span: _ } = program else { unreachable!() };
+        let Node::Function { name: _, arg_types: _, return_type: _, variables: _, statement, span } = &functions[0] else { unreachable!() };
+        assert_eq!(*span, Span::new(0, 0, 3, 1));
+        let Node::Block { statements, span } = statement.as_ref() else { unreachable!() };
+        assert_eq!(*span, Span::new(0, 8, 3, 1));
+        assert_eq!(*statements[0].span(), Span::new(1, 4, 1, 14));
+        let Node::Return { node, span } = &statements[1] else { unreachable!() };
+        assert_eq!(*span, Span::new(2, 4, 2, 17));
+        let Node::Operator { typ: _, lhs, rhs, span } = node.as_ref() else { unreachable!() };
+        assert_eq!(*span, Span::new(2, 4, 2, 15));
+        assert_eq!(*lhs.span(), Span::new(2, 4, 2, 5));
+        assert_eq!(*rhs.span(), Span::new(2, 9, 2, 14));
+    }
+
+    #[test]
+    fn errors_are_collected_and_the_rest_is_still_parsed() {
+        let src = "let g = ;\nfn f[a b] { a @ }\nfn main {\n    let a = 1 +;\n    if a == { a; }\n    a = 2;\n    a @\n}\nfn g { 0 @ }";
+        let (program, errors) = parse(tokenize(src).unwrap());
+        let positions = errors.iter().map(|error| error.span.as_ref().map(|span| (span.line, span.pos))).collect::<Vec<Option<(usize, usize)>>>();
+        assert_eq!(positions, vec![Some((0, 8)), Some((1, 7)), Some((3, 15)), Some((4, 12))]);
+
+        let Node::Program { globals: _, functions, span: _ } = program else { unreachable!() };
+        let names = functions.iter().map(|function| if let Node::Function { name, arg_types: _, return_type: _, variables: _, statement: _, span: _ } = function { name.as_str() } else { unreachable!() }).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["main", "g"]);
+        let Node::Function { name: _, arg_types: _, return_type: _, variables: _, statement, span: _ } = &functions[0] else { unreachable!() };
+        let Node::Block { statements, span: _ } = statement.as_ref() else { unreachable!() };
+        assert_eq!(statements.len(), 2);
+    }
+
+    #[test]
+    fn an_unclosed_block_is_reported_once() {
+        let (program, errors) = parse(tokenize("fn main { if 1 { 0 @\nfn g { 0 @ }").unwrap());
+        assert_eq!(errors.len(), 1);
+        let Node::Program { globals: _, functions, span: _ } = program else { unreachable!() };
+        assert_eq!(functions.len(), 2);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::compile_error::{CompileError, ErrorKind, Span};
+use crate::parser::node::Node;
+use crate::parser::node::logical::Logical;
+use crate::parser::node::operator::Operator;
+use crate::parser::node::value_type::ValueType;
+
+// Folds an initializer into a literal, with the same results the generated code would give at run time; the literal keeps the span of the expression it replaces.
+pub fn evaluate(node: &Node) -> Result<Node, CompileError> {
+    let span = node.span().clone();
+
+    Ok(match node {
+        Node::Number { num: _, span: _ } | Node::Float { num: _, span: _ } | Node::Str { text: _, span: _ } => node.clone(),
+        Node::Cast { typ, node, span: _ } => match (typ, evaluate(node)?) {
+            (ValueType::Int, Node::Number { num, span: _ }) => Node::Number { num, span },
+            (ValueType::Int, Node::Float { num, span: _ }) => Node::Number { num: num as i64, span },
+            (ValueType::Float, Node::Number { num, span: _ }) => Node::Float { num: num as f64, span },
+            (ValueType::Float, Node::Float { num, span: _ }) => Node::Float { num, span },
+            _ => return Err(CompileError::new(ErrorKind::TypeMismatch, "type mismatch: expected int or float, found str", node.span().clone())),
+        },
+        Node::Operator { typ, lhs, rhs, span: _ } if !matches!(typ, Operator::Assign | Operator::ChangeMin | Operator::ChangeMax | Operator::Exchange) => {
+            match (evaluate(lhs)?, evaluate(rhs)?) {
+                (Node::Number { num: lhs, span: _ }, Node::Number { num: rhs, span: _ }) => int_operator(typ, lhs, rhs, span)?,
+                (Node::Number { num: lhs, span: _ }, Node::Float { num: rhs, span: _ }) => float_operator(typ, lhs as f64, rhs, span)?,
+                (Node::Float { num: lhs, span: _ }, Node::Number { num: rhs, span: _ }) => float_operator(typ, lhs, rhs as f64, span)?,
+                (Node::Float { num: lhs, span: _ }, Node::Float { num: rhs, span: _ }) => float_operator(typ, lhs, rhs, span)?,
+                _ => return Err(CompileError::new(ErrorKind::TypeMismatch, "type mismatch: expected int or float, found str", span)),
+            }
+        },
+        Node::Conditional { condition, true_case, false_case, span: _ } => {
+            let (taken, other) = if truth(&evaluate(condition)?)? { (true_case, false_case) } else { (false_case, true_case) };
+            match (evaluate(taken)?, evaluate(other)) {
+                (Node::Number { num, span: _ }, Ok(Node::Float { num: _, span: _ })) => Node::Float { num: num as f64, span },
+                (value, _) => value,
+            }
+        },
+        Node::Logical { typ, lhs, rhs, span: _ } => {
+            let lhs = truth(&evaluate(lhs)?)?;
+            let rhs = truth(&evaluate(rhs)?)?;
+            Node::Number { num: match typ { Logical::And => lhs && rhs, Logical::Or => lhs || rhs } as i64, span }
+        },
+        _ => return Err(CompileError::new(ErrorKind::NotConstant, "not a constant expression", span)),
+    })
+}
+
+fn truth(node: &Node) -> Result<bool, CompileError> {
+    match node {
+        Node::Number { num, span: _ } => Ok(*num != 0),
+        Node::Float { num, span: _ } => Ok(*num != 0.0),
+        _ => Err(CompileError::new(ErrorKind::TypeMismatch, "type mismatch: a condition must be int or float, found str", node.span().clone())),
+    }
+}
+
+fn int_operator(typ: &Operator, lhs: i64, rhs: i64, span: Span) -> Result<Node, CompileError> {
+    let num = match typ {
+        Operator::Add => lhs.wrapping_add(rhs),
+        Operator::Sub => lhs.wrapping_sub(rhs),
+        Operator::Mul => lhs.wrapping_mul(rhs),
+        Operator::Div | Operator::Rem if rhs == 0 => return Err(CompileError::new(ErrorKind::DivisionByZero, "division by zero", span)),
+        Operator::Div => lhs.wrapping_div(rhs),
+        Operator::Rem => lhs.wrapping_rem(rhs),
+        Operator::Power => power(lhs, rhs),
+        Operator::Root => root(lhs, rhs),
+        Operator::And => lhs & rhs,
+        Operator::Xor => lhs ^ rhs,
+        Operator::Or => lhs | rhs,
+        Operator::LShift => lhs.wrapping_shl(rhs as u32),
+        Operator::RShift => lhs.wrapping_shr(rhs as u32),
+        Operator::Equal => (lhs == rhs) as i64,
+        Operator::Less => (lhs < rhs) as i64,
+        _ => return Err(CompileError::new(ErrorKind::NotConstant, "not a constant expression", span)),
+    };
+
+    Ok(Node::Number { num, span })
+}
+
+fn float_operator(typ: &Operator, lhs: f64, rhs: f64, span: Span) -> Result<Node, CompileError> {
+    let num = match typ {
+        Operator::Add => lhs + rhs,
+        Operator::Sub => lhs - rhs,
+        Operator::Mul => lhs * rhs,
+        Operator::Div => lhs / rhs,
+        Operator::Rem => lhs % rhs,
+        Operator::Power => lhs.powf(rhs),
+        Operator::Root => float_root(lhs, rhs),
+        Operator::Equal => return Ok(Node::Number { num: (lhs == rhs) as i64, span }),
+        Operator::Less => return Ok(Node::Number { num: (lhs < rhs) as i64, span }),
+        Operator::And | Operator::Xor | Operator::Or | Operator::LShift | Operator::RShift => return Err(CompileError::new(ErrorKind::TypeMismatch, "type mismatch: expected int, found float", span)),
+        _ => return Err(CompileError::new(ErrorKind::NotConstant, "not a constant expression", span)),
+    };
+
+    Ok(Node::Float { num, span })
+}
+
+// Mirrors `@builtin.power`.
+fn power(base: i64, exponent: i64) -> i64 {
+    if exponent < 0 {
+        return match base {
+            1 => 1,
+            -1 => if exponent % 2 == 0 { 1 } else { -1 },
+            _ => 0,
+        };
+    }
+
+    let (mut acc, mut base, mut exponent) = (1i64, base, exponent);
+    while exponent != 0 {
+        if exponent & 1 == 1 {
+            acc = acc.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+
+    acc
+}
+
+// Mirrors `@builtin.root`.
+fn root(x: i64, index: i64) -> i64 {
+    if index < 0 {
+        return match root_of_index(x, index.unsigned_abs()) {
+            1 => 1,
+            -1 => -1,
+            _ => 0,
+        };
+    }
+
+    root_of_index(x, index as u64)
+}
+
+fn root_of_index(x: i64, index: u64) -> i64 {
+    let negative = x < 0;
+    if index == 0 || (negative && index.is_multiple_of(2)) {
+        return 0;
+    }
+
+    let u = x.unsigned_abs();
+    let root = if index == 1 {
+        u
+    } else if index >= 64 {
+        (u != 0) as u64
+    } else {
+        let (mut lo, mut hi) = (0u64, u32::MAX as u64);
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if root_fits(mid, index, u) {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        lo
+    };
+
+    if negative { (root as i64).wrapping_neg() } else { root as i64 }
+}
+
+fn root_fits(root: u64, index: u64, u: u64) -> bool {
+    let mut acc = 1u64;
+    for _ in 0..index {
+        match acc.checked_mul(root) {
+            Some(next) if next <= u => acc = next,
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+// Mirrors `@builtin.root.float`.
+fn float_root(x: f64, index: f64) -> f64 {
+    let integral = index as i64;
+    let mirror = integral as f64 == index && integral % 2 != 0 && x < 0.0;
+    let root = (if mirror { -x } else { x }).powf(1.0 / index);
+
+    if mirror { -root } else { root }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::constant::{float_root, power, root};
+
+    #[test]
+    fn power_wraps_and_truncates_negative_exponents() {
+        assert_eq!(power(3, 4), 81);
+        assert_eq!(power(-2, 3), -8);
+        assert_eq!(power(7, 0), 1);
+        assert_eq!(power(2, 63), i64::MIN);
+        assert_eq!(power(2, 64), 0);
+
+        assert_eq!(power(2, -1), 0);
+        assert_eq!(power(0, -1), 0);
+        assert_eq!(power(1, -5), 1);
+        assert_eq!(power(-1, -3), -1);
+        assert_eq!(power(-1, -2), 1);
+        assert_eq!(power(-1, i64::MIN), 1);
+    }
+
+    #[test]
+    fn root_truncates_toward_zero() {
+        assert_eq!(root(10, 2), 3);
+        assert_eq!(root(-27, 3), -3);
+        assert_eq!(root(7, 1), 7);
+        assert_eq!(root(i64::MAX, 2), 3037000499);
+        assert_eq!(root(i64::MIN, 3), -2097152);
+        assert_eq!(root(i64::MIN, 63), -2);
+        assert_eq!(root(5, 64), 1);
+        assert_eq!(root(0, 64), 0);
+    }
+
+    #[test]
+    fn root_without_an_integer_answer_is_zero() {
+        assert_eq!(root(-4, 2), 0);
+        assert_eq!(root(5, 0), 0);
+        assert_eq!(root(-5, 0), 0);
+    }
+
+    #[test]
+    fn root_of_a_negative_index_truncates_the_reciprocal() {
+        assert_eq!(root(8, -3), 0);
+        assert_eq!(root(1, -3), 1);
+        assert_eq!(root(-1, -3), -1);
+        assert_eq!(root(-1, -2), 0);
+
+        assert_eq!(root(8, i64::MIN), 1);
+        assert_eq!(root(1, i64::MIN), 1);
+        assert_eq!(root(0, i64::MIN), 0);
+        assert_eq!(root(-1, i64::MIN), 0);
+    }
+
+    #[test]
+    fn float_root_takes_the_real_root_of_an_odd_index() {
+        assert_eq!(float_root(-8.0, 3.0), -2.0);
+        assert_eq!(float_root(16.0, 2.0), 4.0);
+        assert!(float_root(-4.0, 2.0).is_nan());
+    }
+}
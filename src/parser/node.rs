@@ -1,19 +1,83 @@
+use crate::compile_error::Span;
+use crate::parser::node::logical::Logical;
 use crate::parser::node::operator::Operator;
+use crate::parser::node::value_type::ValueType;
 
+pub mod logical;
 pub mod operator;
+pub mod value_type;
 
 #[derive(Debug, Clone)]
 pub enum Node {
-    Program { functions: Vec<Node> },
-    Function { name: String, args_num: usize, variables: Vec<String>, statement: Box<Node> },
-    Statement { node: Box<Node> },
-    Block { statements: Vec<Node> },
-    Return { node: Box<Node> },
-    If { condition: Box<Node>, true_case: Box<Node>, false_case: Box<Option<Node>> },
-    For { init: Box<Option<Node>>, condition: Box<Option<Node>>, update: Box<Option<Node>>, statement: Box<Node> },
-    While { condition: Box<Node>, node: Box<Node> },
-    Operator { typ: Operator, lhs: Box<Node>, rhs: Box<Node> },
-    Variable { offset: usize },
-    FuncCall { function_name: String, arguments: Vec<Node> },
-    Number { num: i64 }
+    Program { globals: Vec<Node>, functions: Vec<Node>, span: Span },
+    Global { name: String, typ: ValueType, value: Box<Node>, span: Span },
+    Function { name: String, arg_types: Vec<ValueType>, return_type: ValueType, variables: Vec<String>, statement: Box<Node>, span: Span },
+    Statement { node: Box<Node>, span: Span },
+    Block { statements: Vec<Node>, span: Span },
+    Return { node: Box<Node>, span: Span },
+    Let { offset: usize, typ: Option<ValueType>, value: Box<Node>, span: Span },
+    If { condition: Box<Node>, true_case: Box<Node>, false_case: Box<Option<Node>>, span: Span },
+    For { label: Option<String>, init: Box<Option<Node>>, condition: Box<Option<Node>>, update: Box<Option<Node>>, statement: Box<Node>, span: Span },
+    While { label: Option<String>, condition: Box<Node>, node: Box<Node>, span: Span },
+    DoWhile { label: Option<String>, node: Box<Node>, condition: Box<Node>, span: Span },
+    Loop { label: Option<String>, node: Box<Node>, span: Span },
+    Match { value: Box<Node>, arms: Vec<(Vec<(i64, i64)>, Node)>, default: Box<Option<Node>>, span: Span },
+    Break { label: Option<String>, span: Span },
+    Continue { label: Option<String>, span: Span },
+    Operator { typ: Operator, lhs: Box<Node>, rhs: Box<Node>, span: Span },
+    Logical { typ: Logical, lhs: Box<Node>, rhs: Box<Node>, span: Span },
+    Conditional { condition: Box<Node>, true_case: Box<Node>, false_case: Box<Node>, span: Span },
+    CompoundAssign { op: Operator, target: Box<Node>, value: Box<Node>, span: Span },
+    Increment { op: Operator, prefix: bool, target: Box<Node>, span: Span },
+    Variable { offset: usize, span: Span },
+    GlobalVariable { index: usize, span: Span },
+    Index { array: Box<Node>, index: Box<Node>, span: Span },
+    Array { typ: ValueType, len: usize, span: Span },
+    FuncCall { function_name: String, arguments: Vec<Node>, span: Span },
+    Cast { typ: ValueType, node: Box<Node>, span: Span },
+    Number { num: i64, span: Span },
+    Float { num: f64, span: Span },
+    Str { text: Vec<u8>, span: Span },
+}
+
+impl Node {
+    // Whether the node names a place that can be written to, which is what every assignment-like operator needs on its left.
+    pub fn is_assignable(&self) -> bool {
+        matches!(self, Node::Variable { offset: _, span: _ } | Node::GlobalVariable { index: _, span: _ } | Node::Index { array: _, index: _, span: _ })
+    }
+
+    // From the first token the node was parsed from through the last one.
+    pub fn span(&self) -> &Span {
+        match self {
+            Node::Program { globals: _, functions: _, span } => span,
+            Node::Global { name: _, typ: _, value: _, span } => span,
+            Node::Function { name: _, arg_types: _, return_type: _, variables: _, statement: _, span } => span,
+            Node::Statement { node: _, span } => span,
+            Node::Block { statements: _, span } => span,
+            Node::Return { node: _, span } => span,
+            Node::Let { offset: _, typ: _, value: _, span } => span,
+            Node::If { condition: _, true_case: _, false_case: _, span } => span,
+            Node::For { label: _, init: _, condition: _, update: _, statement: _, span } => span,
+            Node::While { label: _, condition: _, node: _, span } => span,
+            Node::DoWhile { label: _, node: _, condition: _, span } => span,
+            Node::Loop { label: _, node: _, span } => span,
+            Node::Match { value: _, arms: _, default: _, span } => span,
+            Node::Break { label: _, span } => span,
+            Node::Continue { label: _, span } => span,
+            Node::Operator { typ: _, lhs: _, rhs: _, span } => span,
+            Node::Logical { typ: _, lhs: _, rhs: _, span } => span,
+            Node::Conditional { condition: _, true_case: _, false_case: _, span } => span,
+            Node::CompoundAssign { op: _, target: _, value: _, span } => span,
+            Node::Increment { op: _, prefix: _, target: _, span } => span,
+            Node::Variable { offset: _, span } => span,
+            Node::GlobalVariable { index: _, span } => span,
+            Node::Index { array: _, index: _, span } => span,
+            Node::Array { typ: _, len: _, span } => span,
+            Node::FuncCall { function_name: _, arguments: _, span } => span,
+            Node::Cast { typ: _, node: _, span } => span,
+            Node::Number { num: _, span } => span,
+            Node::Float { num: _, span } => span,
+            Node::Str { text: _, span } => span,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,5 @@
+#[derive(Debug, Clone)]
+pub enum Logical {
+    And,
+    Or,
+}
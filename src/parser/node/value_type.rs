@@ -0,0 +1,39 @@
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ValueType {
+    Int,
+    Float,
+    Str,
+    IntArray,
+    FloatArray,
+}
+
+impl ValueType {
+    pub fn is_number(self) -> bool {
+        matches!(self, ValueType::Int | ValueType::Float)
+    }
+
+    pub fn array(self) -> ValueType {
+        match self {
+            ValueType::Float => ValueType::FloatArray,
+            _ => ValueType::IntArray,
+        }
+    }
+
+    pub fn element(self) -> Option<ValueType> {
+        match self {
+            ValueType::IntArray => Some(ValueType::Int),
+            ValueType::FloatArray => Some(ValueType::Float),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            ValueType::Int => "int",
+            ValueType::Float => "float",
+            ValueType::Str => "str",
+            ValueType::IntArray => "int[]",
+            ValueType::FloatArray => "float[]",
+        }
+    }
+}
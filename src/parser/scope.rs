@@ -0,0 +1,42 @@
+use crate::parser::node::Node;
+
+// The slots of the function being parsed and the names visible from the current block.
+// A global name stands for the node that replaces it: a literal for a const, a `GlobalVariable` otherwise.
+#[derive(Default)]
+pub struct Scope {
+    pub variables: Vec<String>,
+    pub globals: Vec<(String, Node)>,
+    visible: Vec<usize>,
+    blocks: Vec<usize>,
+}
+
+impl Scope {
+    // A scope for a function body that sees the globals declared so far.
+    pub fn function_scope(&self) -> Scope {
+        Scope { globals: self.globals.clone(), ..Scope::default() }
+    }
+
+    // Every declaration gets a fresh slot, so a shadowed variable keeps its own.
+    pub fn declare(&mut self, name: &str) -> usize {
+        self.variables.push(name.to_string());
+        self.visible.push(self.variables.len() - 1);
+        self.variables.len() - 1
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.visible.iter().rev().find(|offset| self.variables[**offset] == name).copied()
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Node> {
+        self.globals.iter().find(|(global, _)| global == name).map(|(_, node)| node)
+    }
+
+    pub fn enter(&mut self) {
+        self.blocks.push(self.visible.len());
+    }
+
+    pub fn leave(&mut self) {
+        let len = self.blocks.pop().unwrap();
+        self.visible.truncate(len);
+    }
+}
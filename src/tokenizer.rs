@@ -1,106 +1,275 @@
+use crate::compile_error::{CompileError, ErrorKind, Span};
+use crate::tokenizer::cursor::Cursor;
 use crate::tokenizer::token::Token;
 use crate::tokenizer::token::token_type::symbol::Symbol;
 use crate::tokenizer::token::token_type::TokenType;
 use crate::tokenizer::token::token_type::word::Word;
 
+pub mod cursor;
 pub mod token;
 
-pub fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+// A single pass over the source: every char is looked at a bounded number of times, so the time taken grows linearly with its length.
+pub fn tokenize(src: &str) -> Result<Vec<Token>, CompileError> {
+    let symbols = Symbol::get_len_order_list();
+    let symbol_chars = Symbol::get_symbol_char_list();
+    let words = Word::get_list();
+
     let mut tokens = Vec::new();
+    let mut cursor = Cursor::new(src);
 
-    let mut line = 0;
-    let mut pos = 0;
+    while let Some(c) = cursor.peek() {
+        let (line, pos) = (cursor.line, cursor.pos);
 
-    let mut index = 0;
-    while index < src.len() {
-        match src.chars().nth(index).unwrap() {
-            '\n' => {
-                index += 1;
-                line += 1;
-                pos = 0;
+        let new_token_type = match c {
+            '\n' | ' ' | '\t' | '\r' => {
+                cursor.bump();
+                continue;
             },
-            ' ' | '\t' | '\r' => {
-                index += 1;
-                pos += 1;
+            '#' => {
+                cursor.eat_while(|c| c != '\n');
+                continue;
             },
-            '0'..='9' => {
-                let (new_token_type, len) = create_number_token(&src[src.char_indices().nth(index).unwrap().0..]);
-
-                tokens.push(Token::new(new_token_type, line, pos));
-
-                index += len;
-                pos += len;
+            '/' if cursor.rest().starts_with("/*") => {
+                skip_block_comment(&mut cursor).ok_or_else(|| CompileError::new(ErrorKind::UnterminatedComment, "unterminated comment", Span::new(line, pos, line, pos + 2)))?;
+                continue;
             },
-            _ => {
-                let new_token_type = create_token(&src[src.char_indices().nth(index).unwrap().0..]);
-                if let Some(new_token_type) = new_token_type {
-                    tokens.push(Token::new(new_token_type.clone(), line, pos));
-
-                    let token_len = new_token_type.get_len();
-                    index += token_len;
-                    pos += token_len;
-                } else {
-                    let message = format!("Undefined Token({}:{})", line, pos);
-                    return Err(message);
-                }
+            '0'..='9' => create_number_token(&mut cursor).map_err(|error| error.located(Span::at(line, pos)))?,
+            '"' => {
+                cursor.bump();
+                create_string_token(&mut cursor).map_err(|error| error.located(Span::at(line, pos)))?
             },
-        }
+            '\'' => {
+                cursor.bump();
+                create_label_token(&mut cursor, &symbol_chars, &words).ok_or_else(|| CompileError::new(ErrorKind::UndefinedToken, "undefined token", Span::new(line, pos, line, pos + 1)))?
+            },
+            _ if symbol_chars.contains(&c) => {
+                create_symbol_token(&mut cursor, &symbols).ok_or_else(|| CompileError::new(ErrorKind::UndefinedToken, "undefined token", Span::new(line, pos, line, pos + 1)))?
+            },
+            _ => create_word_token(&mut cursor, &symbol_chars, &words),
+        };
+
+        // No token spans a newline, so its length is how far the column moved.
+        tokens.push(Token::new(new_token_type, line, pos, cursor.pos - pos));
     }
 
-    tokens.push(Token::new(TokenType::Eof, line, pos));
+    tokens.push(Token::new(TokenType::Eof, cursor.line, cursor.pos, 0));
 
     Ok(tokens)
 }
 
-fn create_token(target: &str) -> Option<TokenType> {
-    let first_char = target.chars().next().unwrap();
-    if Symbol::get_symbol_char_list().contains(&first_char) {
-        create_symbol_token(target)
-    } else {
-        Some(create_word_token(target))
+// Skips a `/* ... */` comment, which may nest; `None` when the source ends inside it.
+fn skip_block_comment(cursor: &mut Cursor) -> Option<()> {
+    let mut depth = 0;
+    loop {
+        if cursor.rest().starts_with("/*") {
+            depth += 1;
+            cursor.skip(2);
+        } else if cursor.rest().starts_with("*/") {
+            depth -= 1;
+            cursor.skip(2);
+            if depth == 0 {
+                return Some(());
+            }
+        } else {
+            cursor.bump()?;
+        }
     }
 }
 
-fn create_symbol_token(target: &str) -> Option<TokenType> {
-    let list = Symbol::get_len_order_list();
-    let symbol = list.iter().find(|symbol| target.starts_with(symbol.to_str()));
-    if let Some(symbol) = symbol {
-        Some(TokenType::Symbol(*symbol))
+// The longest symbol the source continues with.
+fn create_symbol_token(cursor: &mut Cursor, symbols: &[Symbol]) -> Option<TokenType> {
+    let symbol = *symbols.iter().find(|symbol| cursor.rest().starts_with(symbol.to_str()))?;
+    cursor.skip(symbol.to_str().len());
+
+    Some(TokenType::Symbol(symbol))
+}
+
+// A word runs up to whitespace, a comment or a symbol.
+fn create_word_token(cursor: &mut Cursor, symbol_chars: &[char], words: &[Word]) -> TokenType {
+    let word = cursor.eat_while(|c| !(c == ' ' || c == '\n' || c == '\t' || c == '\r' || c == '#' || symbol_chars.contains(&c)));
+
+    let reserved = words.iter().find(|reserved| word == reserved.to_str());
+    if let Some(reserved) = reserved {
+        TokenType::Word(*reserved)
     } else {
+        TokenType::Ident(word.to_string())
+    }
+}
+
+fn create_label_token(cursor: &mut Cursor, symbol_chars: &[char], words: &[Word]) -> Option<TokenType> {
+    let label = match create_word_token(cursor, symbol_chars, words) {
+        TokenType::Word(word) => word.to_str().to_string(),
+        TokenType::Ident(ident) => ident,
+        _ => return None,
+    };
+
+    if label.is_empty() {
         None
+    } else {
+        Some(TokenType::Label(label))
     }
 }
 
-fn create_word_token(target: &str) -> TokenType {
-    let mut word = String::new();
-    for i in 0..target.len() {
-        let c = target.chars().nth(i).unwrap();
-        if c == ' ' || c == '\n' || c == '\t' || Symbol::get_symbol_char_list().contains(&c) {
-            let list = Word::get_list();
-            let reserved = list.iter().find(|reserved| word == reserved.to_str().to_string());
-            return if let Some(reserved) = reserved {
-                TokenType::Word(*reserved)
-            } else {
-                TokenType::Ident(word)
-            }
+// Reads the literal after its opening quote, up to and including the closing one, returning its bytes.
+fn create_string_token(cursor: &mut Cursor) -> Result<TokenType, CompileError> {
+    let mut text = Vec::new();
+
+    loop {
+        let c = cursor.bump().ok_or_else(|| CompileError::unlocated(ErrorKind::UnterminatedString, "unterminated string"))?;
+        match c {
+            '"' => return Ok(TokenType::Str(text)),
+            '\n' => return Err(CompileError::unlocated(ErrorKind::UnterminatedString, "unterminated string")),
+            '\\' => {
+                let escaped = cursor.bump().ok_or_else(|| CompileError::unlocated(ErrorKind::UnterminatedString, "unterminated string"))?;
+                match escaped {
+                    'n' => text.push(b'\n'),
+                    't' => text.push(b'\t'),
+                    '"' => text.push(b'"'),
+                    '\\' => text.push(b'\\'),
+                    'x' => {
+                        let digits = (0..2).filter_map(|_| cursor.bump()).collect::<String>();
+                        // `from_str_radix` alone would also take a sign, as in `\x+1`.
+                        if digits.len() != 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                            return Err(CompileError::unlocated(ErrorKind::UndefinedEscape, "undefined escape"));
+                        }
+                        text.push(u8::from_str_radix(&digits, 16).unwrap());
+                    },
+                    _ => return Err(CompileError::unlocated(ErrorKind::UndefinedEscape, "undefined escape")),
+                }
+            },
+            _ => {
+                let mut buffer = [0; 4];
+                text.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            },
         }
+    }
+}
+
+// Reads a decimal, `0x`, `0b` or `0o` literal with optional `_` separators; a decimal one without a dot is still an int when its exponent is not negative.
+fn create_number_token(cursor: &mut Cursor) -> Result<TokenType, CompileError> {
+    let radix = match (cursor.peek(), cursor.peek_nth(1)) {
+        (Some('0'), Some('x' | 'X')) => 16,
+        (Some('0'), Some('b' | 'B')) => 2,
+        (Some('0'), Some('o' | 'O')) => 8,
+        _ => 10,
+    };
+    if radix != 10 {
+        cursor.skip(2);
+        let digits = eat_digits(cursor, radix).replace('_', "");
+        if digits.is_empty() {
+            return Err(CompileError::unlocated(ErrorKind::InvalidNumber, "invalid number"));
+        }
+        let num = i64::from_str_radix(&digits, radix).map_err(|_| CompileError::unlocated(ErrorKind::OutOfRangeNumber, "out of range number"))?;
+        return Ok(TokenType::Number(num));
+    }
+
+    let start = cursor.offset();
+    eat_digits(cursor, 10);
+    let mut float = false;
+
+    // A dot only continues the literal when a digit follows it.
+    if cursor.peek() == Some('.') && cursor.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) {
+        cursor.bump();
+        eat_digits(cursor, 10);
+        float = true;
+    }
 
-        word.push(c);
+    let mantissa = cursor.since(start).replace('_', "");
+    let mut exponent = None;
+    if let Some('e' | 'E') = cursor.peek() {
+        let sign = if let Some('+' | '-') = cursor.peek_nth(1) { 1 } else { 0 };
+        if cursor.peek_nth(1 + sign).is_some_and(|c| c.is_ascii_digit()) {
+            cursor.bump();
+            let negative = sign == 1 && cursor.bump() == Some('-');
+            exponent = Some((negative, eat_digits(cursor, 10).replace('_', "")));
+        }
     }
 
-    TokenType::Ident(word)
+    let num_str = cursor.since(start).replace('_', "");
+    match exponent {
+        Some((false, exponent)) if !float => {
+            let mantissa = mantissa.parse::<i64>().map_err(|_| CompileError::unlocated(ErrorKind::OutOfRangeNumber, "out of range number"))?;
+            let num = exponent.parse::<u32>().ok().and_then(|exponent| 10i64.checked_pow(exponent)).and_then(|scale| mantissa.checked_mul(scale));
+            Ok(TokenType::Number(num.ok_or_else(|| CompileError::unlocated(ErrorKind::OutOfRangeNumber, "out of range number"))?))
+        },
+        None if !float => Ok(TokenType::Number(num_str.parse().map_err(|_| CompileError::unlocated(ErrorKind::OutOfRangeNumber, "out of range number"))?)),
+        _ => Ok(TokenType::Float(num_str.parse().unwrap())),
+    }
 }
 
-fn create_number_token(target: &str) -> (TokenType, usize) {
-    let mut num_str = String::new();
-    for i in 0..target.len() {
-        let c = target.chars().nth(i).unwrap();
-        if let '0'..='9' = c {
-            num_str.push(c);
-        } else {
-            break;
+// Consumes the digits of the given radix, including `_` separators.
+fn eat_digits<'a>(cursor: &mut Cursor<'a>, radix: u32) -> &'a str {
+    cursor.eat_while(|c| c.is_digit(radix) || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compile_error::{ErrorKind, Span};
+    use crate::tokenizer::tokenize;
+    use crate::tokenizer::token::token_type::symbol::Symbol;
+    use crate::tokenizer::token::token_type::TokenType;
+
+    fn token_types(src: &str) -> Vec<TokenType> {
+        tokenize(src).unwrap().into_iter().map(|token| token.typ).collect()
+    }
+
+    fn number(src: &str) -> TokenType {
+        let types = token_types(src);
+        assert!(types.len() == 2, "`{}` should be a single token", src);
+        types[0].clone()
+    }
+
+    #[test]
+    fn numbers_in_every_radix() {
+        assert!(number("42") == TokenType::Number(42));
+        assert!(number("0x1F") == TokenType::Number(31));
+        assert!(number("0XfF") == TokenType::Number(255));
+        assert!(number("0b1010") == TokenType::Number(10));
+        assert!(number("0B11") == TokenType::Number(3));
+        assert!(number("0o17") == TokenType::Number(15));
+        assert!(number("0O777") == TokenType::Number(511));
+        assert!(number("0x7fff_ffff_ffff_ffff") == TokenType::Number(i64::MAX));
+    }
+
+    #[test]
+    fn separators_are_ignored() {
+        assert!(number("1_000_000") == TokenType::Number(1_000_000));
+        assert!(number("0b1111_0000") == TokenType::Number(240));
+        assert!(number("0x_ff") == TokenType::Number(255));
+        assert!(number("1_0.2_5") == TokenType::Float(10.25));
+        assert!(number("1e1_0") == TokenType::Number(10_000_000_000));
+    }
+
+    #[test]
+    fn an_exponent_keeps_an_int_unless_it_is_negative() {
+        assert!(number("1e9") == TokenType::Number(1_000_000_000));
+        assert!(number("3E2") == TokenType::Number(300));
+        assert!(number("1e+2") == TokenType::Number(100));
+        assert!(number("1e-9") == TokenType::Float(1e-9));
+        assert!(number("2.5e3") == TokenType::Float(2500.0));
+        assert!(number("1.5") == TokenType::Float(1.5));
+    }
+
+    #[test]
+    fn a_dot_or_exponent_without_digits_ends_the_number() {
+        assert!(token_types("1..5") == vec![TokenType::Number(1), TokenType::Symbol(Symbol::Range), TokenType::Number(5), TokenType::Eof]);
+        assert!(token_types("2e") == vec![TokenType::Number(2), TokenType::Ident("e".to_string()), TokenType::Eof]);
+    }
+
+    #[test]
+    fn a_prefix_without_digits_is_invalid() {
+        for src in ["0x", "0b", "0o", "0x_", "0b2"] {
+            let error = tokenize(src).err().unwrap_or_else(|| panic!("`{}` should not tokenize", src));
+            assert_eq!(error.kind, ErrorKind::InvalidNumber, "{}", src);
         }
     }
 
-    (TokenType::Number(num_str.parse().unwrap()), num_str.len())
-}
\ No newline at end of file
+    #[test]
+    fn an_overflowing_number_reports_where_it_starts() {
+        for src in ["9223372036854775808", "0x1_0000_0000_0000_0000", "1e19", "10e18"] {
+            let error = tokenize(&format!("fn main {{\n  a = {};\n}}", src)).err().unwrap_or_else(|| panic!("`{}` should not tokenize", src));
+            assert_eq!(error.kind, ErrorKind::OutOfRangeNumber, "{}", src);
+            assert_eq!(error.span, Some(Span::at(1, 6)), "{}", src);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::str::CharIndices;
+
+// Walks the source once, keeping the line and column of the next char; looking ahead copies the iterator instead of rescanning from the start.
+pub struct Cursor<'a> {
+    src: &'a str,
+    chars: CharIndices<'a>,
+    pub line: usize,
+    pub pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Cursor { src, chars: src.char_indices(), line: 0, pos: 0 }
+    }
+
+    pub fn peek(&self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    pub fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n).map(|(_, c)| c)
+    }
+
+    // The source from the next char on.
+    pub fn rest(&self) -> &'a str {
+        self.chars.as_str()
+    }
+
+    pub fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.pos = 0;
+        } else {
+            self.pos += 1;
+        }
+
+        Some(c)
+    }
+
+    pub fn skip(&mut self, n: usize) {
+        for _ in 0..n {
+            self.bump();
+        }
+    }
+
+    // Consumes chars as long as `predicate` holds and returns them as a slice of the source.
+    pub fn eat_while(&mut self, mut predicate: impl FnMut(char) -> bool) -> &'a str {
+        let start = self.offset();
+        while self.peek().is_some_and(&mut predicate) {
+            self.bump();
+        }
+
+        self.since(start)
+    }
+
+    // The byte offset of the next char.
+    pub fn offset(&self) -> usize {
+        self.chars.offset()
+    }
+
+    // The source consumed since the byte offset `start`.
+    pub fn since(&self, start: usize) -> &'a str {
+        &self.src[start..self.offset()]
+    }
+}
@@ -1,3 +1,4 @@
+use crate::compile_error::Span;
 use crate::tokenizer::token::token_type::TokenType;
 
 pub mod token_type;
@@ -6,10 +7,15 @@ pub struct Token {
     pub typ: TokenType,
     pub line: usize,
     pub pos: usize,
+    pub len: usize,
 }
 
 impl Token {
-    pub fn new(typ: TokenType, line: usize, pos: usize) -> Self {
-        Token { typ, line, pos }
+    pub fn new(typ: TokenType, line: usize, pos: usize, len: usize) -> Self {
+        Token { typ, line, pos, len }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.line, self.pos, self.line, self.pos + self.len)
     }
 }
\ No newline at end of file
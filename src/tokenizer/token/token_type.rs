@@ -4,23 +4,30 @@ use crate::tokenizer::token::token_type::word::Word;
 pub mod symbol;
 pub mod word;
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum TokenType {
     Symbol(Symbol),
     Word(Word),
     Ident(String),
+    Label(String),
     Number(i64),
+    Float(f64),
+    Str(Vec<u8>),
     Eof,
 }
 
 impl TokenType {
-    pub fn get_len(&self) -> usize {
+    // How the token is named in an "expected ..., found ..." message.
+    pub fn describe(&self) -> String {
         match &self {
-            TokenType::Symbol(symbol) => symbol.to_str().len(),
-            TokenType::Word(word) => word.to_str().len(),
-            TokenType::Ident(ident) => ident.len(),
-            TokenType::Number(_) => 0,
-            TokenType::Eof => 0,
+            TokenType::Symbol(symbol) => format!("`{}`", symbol.to_str()),
+            TokenType::Word(word) => format!("`{}`", word.to_str()),
+            TokenType::Ident(ident) => format!("`{}`", ident),
+            TokenType::Label(label) => format!("`'{}`", label),
+            TokenType::Number(num) => format!("`{}`", num),
+            TokenType::Float(num) => format!("`{}`", num),
+            TokenType::Str(_) => "a string literal".to_string(),
+            TokenType::Eof => "end of file".to_string(),
         }
     }
 }
\ No newline at end of file
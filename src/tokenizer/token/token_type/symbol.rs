@@ -50,12 +50,18 @@ pub enum Symbol {
     OpenSquare,
     CloseSquare,
     Comma,
+    Colon,
+    Question,
+    Arrow,
+    FatArrow,
+    Range,
+    RangeInclusive,
     Return,
     End,
 }
 
 impl Symbol {
-    pub fn to_str(&self) -> &str {
+    pub fn to_str(self) -> &'static str {
         match self {
             Symbol::Add => "+",
             Symbol::Sub => "-",
@@ -104,6 +110,12 @@ impl Symbol {
             Symbol::OpenSquare => "[",
             Symbol::CloseSquare => "]",
             Symbol::Comma => ",",
+            Symbol::Colon => ":",
+            Symbol::Question => "?",
+            Symbol::Arrow => "->",
+            Symbol::FatArrow => "=>",
+            Symbol::Range => "..",
+            Symbol::RangeInclusive => "..=",
             Symbol::Return => "@",
             Symbol::End => ";",
         }
@@ -8,21 +8,33 @@ pub enum Word {
     Else,
     For,
     While,
+    Do,
     Loop,
+    Match,
+    Let,
+    Const,
+    Break,
+    Continue,
     Int,
     Float,
     String,
 }
 
 impl Word {
-    pub fn to_str(&self) -> &str {
+    pub fn to_str(self) -> &'static str {
         match self {
             Word::Function => "fn",
             Word::If => "if",
             Word::Else => "else",
             Word::For => "for",
             Word::While => "while",
+            Word::Do => "do",
             Word::Loop => "loop",
+            Word::Match => "match",
+            Word::Let => "let",
+            Word::Const => "const",
+            Word::Break => "break",
+            Word::Continue => "continue",
             Word::Int => "int",
             Word::Float => "float",
             Word::String => "str",
@@ -0,0 +1,275 @@
+use crate::compile_error::{CompileError, ErrorKind, Span};
+use crate::parser::node::Node;
+use crate::parser::node::operator::Operator;
+use crate::parser::node::value_type::ValueType;
+
+struct Context<'a> {
+    functions: &'a Vec<(String, Vec<ValueType>, ValueType)>,
+    globals: &'a Vec<ValueType>,
+    variable_types: Vec<Option<ValueType>>,
+    return_type: ValueType,
+}
+
+pub fn check(program: &Node) -> Result<(), CompileError> {
+    if let Node::Program { globals, functions, span: _ } = program {
+        let mut global_types = Vec::new();
+        for global in globals {
+            if let Node::Global { name: _, typ, value: _, span: _ } = global {
+                global_types.push(*typ);
+            } else {
+                return Err(CompileError::internal("Not a global"));
+            }
+        }
+
+        let mut function_info = Vec::new();
+        for function in functions {
+            if let Node::Function { name, arg_types, return_type, variables: _, statement: _, span: _ } = function {
+                function_info.push((name.clone(), arg_types.clone(), *return_type));
+            } else {
+                return Err(CompileError::internal("Not a function"));
+            }
+        }
+
+        for function in functions {
+            if let Node::Function { name: _, arg_types, return_type, variables, statement, span: _ } = function {
+                let mut variable_types = vec![None; variables.len()];
+                for (variable_type, typ) in variable_types.iter_mut().zip(arg_types) {
+                    *variable_type = Some(*typ);
+                }
+
+                let mut context = Context { functions: &function_info, globals: &global_types, variable_types, return_type: *return_type };
+                check_statement(statement, &mut context)?;
+            }
+        }
+
+        Ok(())
+    } else {
+        Err(CompileError::internal("Not a program"))
+    }
+}
+
+fn check_statement(node: &Node, context: &mut Context) -> Result<(), CompileError> {
+    match node {
+        Node::Statement { node, span: _ } => {
+            type_of(node, context)?;
+        },
+        Node::Block { statements, span: _ } => {
+            for statement in statements {
+                check_statement(statement, context)?;
+            }
+        },
+        Node::Return { node, span: _ } => {
+            let typ = type_of(node, context)?;
+            expect(typ, context.return_type, node.span())?;
+        },
+        Node::Let { offset, typ, value, span: _ } => {
+            let found = type_of(value, context)?;
+            let typ = typ.unwrap_or(found);
+            expect(found, typ, value.span())?;
+            context.variable_types[*offset] = Some(typ);
+        },
+        Node::If { condition, true_case, false_case, span: _ } => {
+            check_condition(condition, context)?;
+            check_statement(true_case, context)?;
+            if let Some(false_case) = false_case.as_ref() {
+                check_statement(false_case, context)?;
+            }
+        },
+        Node::For { label: _, init, condition, update, statement, span: _ } => {
+            if let Some(init) = init.as_ref() {
+                check_statement(init, context)?;
+            }
+            if let Some(condition) = condition.as_ref() {
+                check_condition(condition, context)?;
+            }
+            check_statement(statement, context)?;
+            if let Some(update) = update.as_ref() {
+                type_of(update, context)?;
+            }
+        },
+        Node::While { label: _, condition, node, span: _ } => {
+            check_condition(condition, context)?;
+            check_statement(node, context)?;
+        },
+        Node::DoWhile { label: _, node, condition, span: _ } => {
+            check_statement(node, context)?;
+            check_condition(condition, context)?;
+        },
+        Node::Loop { label: _, node, span: _ } => {
+            check_statement(node, context)?;
+        },
+        Node::Match { value, arms, default, span: _ } => {
+            let typ = type_of(value, context)?;
+            if typ != ValueType::Int {
+                return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: expected int, found {}", typ.to_str()), value.span().clone()));
+            }
+            for (_, statement) in arms {
+                check_statement(statement, context)?;
+            }
+            if let Some(default) = default.as_ref() {
+                check_statement(default, context)?;
+            }
+        },
+        Node::Break { label: _, span: _ } | Node::Continue { label: _, span: _ } => (),
+        _ => return Err(CompileError::internal("Not a statement")),
+    }
+
+    Ok(())
+}
+
+fn check_condition(node: &Node, context: &mut Context) -> Result<(), CompileError> {
+    let typ = type_of(node, context)?;
+    if !typ.is_number() {
+        return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: a condition must be int or float, found {}", typ.to_str()), node.span().clone()));
+    }
+
+    Ok(())
+}
+
+fn type_of(node: &Node, context: &mut Context) -> Result<ValueType, CompileError> {
+    Ok(match node {
+        Node::Operator { typ, lhs, rhs, span } => {
+            match typ {
+                Operator::Assign | Operator::ChangeMin | Operator::ChangeMax => {
+                    let value = type_of(rhs, context)?;
+                    let target = target_type(lhs, context)?;
+                    if !matches!(typ, Operator::Assign) && !target.is_number() {
+                        return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: expected int or float, found {}", target.to_str()), lhs.span().clone()));
+                    }
+                    expect(value, target, rhs.span())?;
+                    target
+                },
+                Operator::Exchange => {
+                    let lhs = target_type(lhs, context)?;
+                    let rhs = target_type(rhs, context)?;
+                    if lhs != rhs {
+                        return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: cannot exchange {} with {}", lhs.to_str(), rhs.to_str()), span.clone()));
+                    }
+                    lhs
+                },
+                _ => {
+                    let lhs_type = type_of(lhs, context)?;
+                    let rhs_type = type_of(rhs, context)?;
+                    operator_type(typ, (lhs_type, lhs.span()), (rhs_type, rhs.span()))?
+                },
+            }
+        },
+        Node::CompoundAssign { op, target, value, span } => {
+            let value_type = type_of(value, context)?;
+            let target_type = target_type(target, context)?;
+            let result = operator_type(op, (target_type, target.span()), (value_type, value.span()))?;
+            expect(result, target_type, span)?;
+            target_type
+        },
+        Node::Increment { op: _, prefix: _, target, span: _ } => {
+            let typ = target_type(target, context)?;
+            numeric((typ, target.span()), (ValueType::Int, target.span()))?
+        },
+        Node::Conditional { condition, true_case, false_case, span } => {
+            check_condition(condition, context)?;
+            let true_type = type_of(true_case, context)?;
+            let false_type = type_of(false_case, context)?;
+            match (true_type, false_type) {
+                (lhs, rhs) if lhs == rhs => lhs,
+                (ValueType::Int, ValueType::Float) | (ValueType::Float, ValueType::Int) => ValueType::Float,
+                _ => return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: the branches give {} and {}", true_type.to_str(), false_type.to_str()), span.clone()).with_label(true_case.span().clone(), format!("this is {}", true_type.to_str())).with_label(false_case.span().clone(), format!("this is {}", false_type.to_str()))),
+            }
+        },
+        Node::Logical { typ: _, lhs, rhs, span: _ } => {
+            check_condition(lhs, context)?;
+            check_condition(rhs, context)?;
+            ValueType::Int
+        },
+        Node::Variable { offset, span } => context.variable_types[*offset].ok_or_else(|| CompileError::new(ErrorKind::Internal, "Undeclared variable", span.clone()))?,
+        Node::GlobalVariable { index, span: _ } => context.globals[*index],
+        Node::Index { array, index, span: _ } => {
+            let array_type = type_of(array, context)?;
+            let index_type = type_of(index, context)?;
+            expect(index_type, ValueType::Int, index.span())?;
+            if let Some(element) = array_type.element() {
+                element
+            } else {
+                return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: expected an array, found {}", array_type.to_str()), array.span().clone()));
+            }
+        },
+        Node::Array { typ, len: _, span: _ } => *typ,
+        Node::FuncCall { function_name, arguments, span } => {
+            let mut args = Vec::new();
+            for argument in arguments {
+                args.push(type_of(argument, context)?);
+            }
+
+            // `debug` and `print` take any single int, float or str.
+            if (function_name == "debug" || function_name == "print") && args.len() == 1 {
+                if args[0].element().is_some() {
+                    return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: expected int, float or str, found {}", args[0].to_str()), arguments[0].span().clone()));
+                }
+                return Ok(ValueType::Int);
+            }
+
+            let functions = context.functions;
+            let function = functions.iter().find(|(name, arg_types, _)| name == function_name && arg_types.len() == args.len());
+            if let Some((_, arg_types, return_type)) = function {
+                for ((arg, typ), argument) in args.iter().zip(arg_types).zip(arguments) {
+                    expect(*arg, *typ, argument.span())?;
+                }
+                *return_type
+            } else {
+                return Err(CompileError::new(ErrorKind::FunctionNotFound, format!("function `{}` taking {} arguments not found", function_name, args.len()), span.clone()));
+            }
+        },
+        Node::Cast { typ, node, span: _ } => {
+            let from = type_of(node, context)?;
+            if !from.is_number() {
+                return Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: {} cannot be converted to {}", from.to_str(), typ.to_str()), node.span().clone()));
+            }
+            *typ
+        },
+        Node::Number { num: _, span: _ } => ValueType::Int,
+        Node::Float { num: _, span: _ } => ValueType::Float,
+        Node::Str { text: _, span: _ } => ValueType::Str,
+        _ => return Err(CompileError::internal("Not an expression")),
+    })
+}
+
+// Each operand comes with its span, so a wrong one can be pointed at.
+fn operator_type(typ: &Operator, lhs: (ValueType, &Span), rhs: (ValueType, &Span)) -> Result<ValueType, CompileError> {
+    match typ {
+        Operator::And | Operator::Xor | Operator::Or | Operator::LShift | Operator::RShift => {
+            expect(lhs.0, ValueType::Int, lhs.1)?;
+            expect(rhs.0, ValueType::Int, rhs.1)?;
+            Ok(ValueType::Int)
+        },
+        Operator::Equal | Operator::Less => {
+            numeric(lhs, rhs)?;
+            Ok(ValueType::Int)
+        },
+        _ => numeric(lhs, rhs),
+    }
+}
+
+fn target_type(node: &Node, context: &mut Context) -> Result<ValueType, CompileError> {
+    match node {
+        Node::Variable { offset, span } => context.variable_types[*offset].ok_or_else(|| CompileError::new(ErrorKind::Internal, "Undeclared variable", span.clone())),
+        Node::GlobalVariable { index: _, span: _ } | Node::Index { array: _, index: _, span: _ } => type_of(node, context),
+        _ => Err(CompileError::new(ErrorKind::NotAssignable, "not assignable", node.span().clone())),
+    }
+}
+
+// Both operands of an arithmetic operator have to be numbers, and an int is widened when the other one is a float.
+fn numeric(lhs: (ValueType, &Span), rhs: (ValueType, &Span)) -> Result<ValueType, CompileError> {
+    match (lhs, rhs) {
+        ((typ, span), _) | (_, (typ, span)) if !typ.is_number() => Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: expected int or float, found {}", typ.to_str()), span.clone())),
+        ((ValueType::Float, _), _) | (_, (ValueType::Float, _)) => Ok(ValueType::Float),
+        _ => Ok(ValueType::Int),
+    }
+}
+
+// Whether a value of type `found` can be stored where `expected` is required; an int widens to a float, nothing else converts implicitly.
+fn expect(found: ValueType, expected: ValueType, span: &Span) -> Result<(), CompileError> {
+    if found == expected || (found == ValueType::Int && expected == ValueType::Float) {
+        Ok(())
+    } else {
+        Err(CompileError::new(ErrorKind::TypeMismatch, format!("type mismatch: expected {}, found {}", expected.to_str(), found.to_str()), span.clone()))
+    }
+}